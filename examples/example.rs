@@ -1,10 +1,13 @@
 use flecs_ecs::prelude::*;
 use flecs_wgpu_demo::*;
 use vello::{
-    kurbo::{Affine, Vec2},
+    kurbo::{Affine, Point, Vec2},
     peniko::Color,
 };
-use winit::{event::MouseButton, keyboard::KeyCode};
+use winit::{
+    event::MouseButton,
+    keyboard::{KeyCode, PhysicalKey},
+};
 
 // Type of shape to spawn
 #[derive(Component, Debug)]
@@ -22,6 +25,10 @@ struct ShapeColor(Color, f64);
 #[derive(Component)]
 struct Spawning;
 
+// Scene the UI is drawn into, layered over the canvas
+#[derive(Component)]
+struct UiLayer;
+
 // Created as a singleton when mid-pan
 #[derive(Component)]
 struct Panning {
@@ -39,7 +46,7 @@ trait Spawns: VelloShape {
             let cursor_tf = scene.camera.inverse() * Affine::translate((cursor.x(), cursor.y()));
             let cursor_pos = cursor_tf.translation();
             shape.update(cursor_pos, tf);
-            fill.color = color.0;
+            fill.brush = color.0.into();
         });
     }
 
@@ -76,7 +83,7 @@ impl Module for ExampleModule {
     fn module(world: &World) {
         world.module::<Self>("module");
 
-        system!("draw_ui", world, &mut TextWriter($), &ShapeType($), &ShapeColor($), &Window(up), &mut VelloScene)
+        system!("draw_ui", world, &mut TextWriter($), &ShapeType($), &ShapeColor($), &Window(up), &mut VelloScene, UiLayer)
             .kind::<flecs::pipeline::PostUpdate>()
             .each(|(text, ty, color, window, scene)| {
                 text.add(
@@ -102,12 +109,36 @@ impl Module for ExampleModule {
             });
 
 
-        system!("handle_input", world, &mut ShapeType($), &mut ShapeColor($), &Input($), &flecs_wgpu_demo::Cursor(up), &mut VelloScene)
+        // The UI is drawn over the canvas, so it takes the pointer before the canvas sees it
+        system!("capture_ui", world, &mut Input($), &flecs_wgpu_demo::Cursor, &Window)
+            .kind::<InputCapture>()
+            .each(|(input, cursor, window)| {
+                let height = window.height() as f64;
+                let cursor_pos = Point::new(cursor.x(), cursor.y());
+                let over_ui = [
+                    vello::kurbo::Rect::new(0.0, 0.0, 160.0, 110.0),
+                    vello::kurbo::Rect::new(0.0, height - 64.0, 280.0, height),
+                ]
+                .iter()
+                .any(|rect| rect.contains(cursor_pos));
+
+                if over_ui {
+                    // Only the press is taken so that drags started on the canvas can end over the UI
+                    if input.just_pressed(MouseButton::Left) {
+                        input.consume(MouseButton::Left);
+                    }
+                    input.consume_pointer();
+                }
+            });
+
+        system!("handle_input", world, &ShapeType($), &mut ShapeColor($), &Input($), &flecs_wgpu_demo::Cursor(up), &mut VelloScene, !UiLayer)
             .each_entity(|e, (ty, color, input, cursor, scene)| {
                 let world = e.world();
+                // Shapes picked on the UI layer take the pointer from the canvas
+                let input = input.layer(scene.layer);
                 let cursor_tf = scene.camera.inverse() * Affine::translate((cursor.x(), cursor.y()));
                 let cursor_pos = cursor_tf.translation();
-                if input.just_pressed(MouseButton::Left) && cursor.in_frame() {
+                if input.just_pressed(MouseButton::Left) && cursor.in_frame() && !input.pointer_consumed() {
                     world.scope_id(e, |world| {
                         let shape = world
                             .entity()
@@ -146,18 +177,6 @@ impl Module for ExampleModule {
                     });
                 }
 
-                if input.just_pressed(KeyCode::Digit1) {
-                    *ty = ShapeType::Circle;
-                }
-
-                if input.just_pressed(KeyCode::Digit2) {
-                    *ty = ShapeType::Rectangle;
-                }
-
-                if input.just_pressed(KeyCode::Digit3) {
-                    *ty = ShapeType::Line;
-                }
-
                 if input.pressed(KeyCode::ShiftLeft) || input.pressed(KeyCode::ShiftRight) {
                     color.1 += input.scroll_y() * 10.0;
                     color.0 = Color::hlc(color.1, 80.0, 127.0);
@@ -167,6 +186,29 @@ impl Module for ExampleModule {
                 }
             });
 
+        // Switch shape type on discrete key presses
+        observer!("select_shape", world, KeyDown, &flecs_wgpu_demo::Cursor).each_iter(|it, _, _| {
+            let PhysicalKey::Code(code) = **it.param() else {
+                return;
+            };
+            let ty = match code {
+                KeyCode::Digit1 => ShapeType::Circle,
+                KeyCode::Digit2 => ShapeType::Rectangle,
+                KeyCode::Digit3 => ShapeType::Line,
+                _ => return,
+            };
+            it.world().set(ty);
+        });
+
+        // Outline shapes while they are hovered
+        observer!("outline_hovered", world, PointerEnter, &Transform).each_entity(|e, _| {
+            e.set(Stroke::new(3.0, Color::WHITE));
+        });
+
+        observer!("remove_outline", world, PointerLeave, &Transform).each_entity(|e, _| {
+            e.remove::<Stroke>();
+        });
+
         // Create systems to handle shapes mid-creation
         Rect::spawn_system(world);
         Circle::spawn_system(world);
@@ -188,6 +230,13 @@ fn main() {
                 .entity_named("scene")
                 .child_of_id(window)
                 .set(VelloScene::default());
+            let mut ui = VelloScene::default();
+            ui.layer = 1;
+            world
+                .entity_named("ui")
+                .child_of_id(window)
+                .add::<UiLayer>()
+                .set(ui);
             world.set(ShapeType::Circle);
             world.set(ShapeColor(Color::hlc(180.0, 80.0, 127.0), 180.0));
         });
@@ -1,7 +1,7 @@
 use deref_derive::Deref;
 use flecs_ecs::{core::flecs::rest::Rest, prelude::*};
-use std::error::Error;
-use wgpu::{SurfaceTargetUnsafe, TextureFormat};
+use std::{error::Error, sync::Arc};
+use wgpu::SurfaceTargetUnsafe;
 use winit::{
     application::ApplicationHandler,
     dpi::PhysicalSize,
@@ -11,9 +11,11 @@ use winit::{
 };
 
 use crate::{
-    render::WGPU,
-    window::{Window, WindowMap},
-    Cursor, Input, RenderModule, TextWriter, VelloShapeModule,
+    recording::fixed_step,
+    render::{RenderBackend, RenderMode, RenderTarget, WGPU},
+    window::{SurfaceSettings, Window, WindowMap},
+    Cursor, Input, InputCapture, PickingModule, RenderModule, SvgModule, TextWriter,
+    VelloShapeModule,
 };
 
 #[derive(Component, Deref)]
@@ -25,15 +27,26 @@ pub struct MainWindow;
 #[derive(Component)]
 pub struct WindowPrefab;
 
-#[derive(Default)]
 pub struct Application {
     pub world: World,
     initialized: bool,
 }
 
+impl Default for Application {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Application {
     pub fn new() -> Self {
-        Self::default()
+        let app = Self {
+            world: World::new(),
+            initialized: false,
+        };
+        // Imported up front so modules imported before initialization can use its input phase
+        app.world.import::<ApplicationModule>();
+        app
     }
 
     pub async fn initial_window(
@@ -54,70 +67,57 @@ impl Application {
                 .expect("Failed to create surface.")
         };
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
-            })
-            .await
-            .expect("Failed to find an appropriate adapter");
-
-        // Create the logical device and command queue
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    label: None,
-                    required_features: wgpu::Features::empty(),
-                    required_limits: wgpu::Limits::default().using_resolution(adapter.limits()),
-                    memory_hints: Default::default(),
-                },
-                None,
-            )
-            .await
-            .expect("Failed to create device");
+        let backend = self
+            .world
+            .try_get::<&RenderBackend>(|backend| *backend)
+            .unwrap_or_default();
+        let wgpu = WGPU::new(instance, Some(&surface), backend).await;
 
         let mut size: winit::dpi::PhysicalSize<u32> = window.inner_size();
         size.width = size.width.max(1);
         size.height = size.height.max(1);
 
         let mut config = surface
-            .get_default_config(&adapter, size.width, size.height)
+            .get_default_config(&wgpu.adapter, size.width, size.height)
             .unwrap();
 
-        // For vello
-        let capabilities = surface.get_capabilities(&adapter);
-        let format = capabilities
-            .formats
-            .into_iter()
-            .find(|it| matches!(it, TextureFormat::Rgba8Unorm | TextureFormat::Bgra8Unorm))
-            .expect("surface should support Rgba8Unorm or Bgra8Unorm");
-        config.format = format;
         config.usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
 
-        surface.configure(&device, &config);
+        let mode = self
+            .world
+            .try_get::<&RenderMode>(|mode| *mode)
+            .unwrap_or_default();
 
-        let wgpu = WGPU {
-            adapter,
-            device,
-            instance,
-            queue,
-            format,
+        let window_id = window.id();
+        let scale_factor = window.scale_factor();
+        let mut window = Window {
+            window,
+            surface: Arc::new(surface),
+            config,
+            redraw: true,
+            texture: None,
+            view: None,
+            screenshots: Vec::new(),
+            pipelined: false,
         };
 
-        let window_id = window.id();
+        let settings = self
+            .world
+            .try_get::<&SurfaceSettings>(|settings| *settings)
+            .unwrap_or_default();
+        window.configure(&wgpu, &settings);
+        // Configured once here, after which the render thread takes over
+        window.pipelined = mode == RenderMode::Pipelined;
+        let target = RenderTarget::new(&wgpu.device, size.width, size.height);
+
         let window_e = self
             .world
             .entity_named("window")
-            .set(Window {
-                window,
-                surface,
-                config,
-                redraw: true,
-                texture: None,
-                view: None,
-            })
-            .is_a::<WindowPrefab>();
+            .set(window)
+            .set(target)
+            .set(settings)
+            .is_a::<WindowPrefab>()
+            .set(Cursor::new(scale_factor));
 
         self.world.set(wgpu);
         self.world.get::<&mut WindowMap>(|map| {
@@ -149,9 +149,10 @@ impl Application {
 
         self.world.add_first::<MainWindow>(initial_window.id());
 
-        self.world.import::<ApplicationModule>();
         self.world.import::<RenderModule>();
         self.world.import::<VelloShapeModule>();
+        self.world.import::<PickingModule>();
+        self.world.import::<SvgModule>();
     }
 
     pub fn run(mut self) -> Result<(), Box<dyn Error>> {
@@ -190,7 +191,10 @@ impl ApplicationHandler<()> for Application {
             }
             WindowEvent::RedrawRequested => {
                 window_e.get::<&mut Window>(|w| w.redraw = true);
-                self.world.progress();
+                match fixed_step(&self.world) {
+                    Some(delta_time) => self.world.progress_time(delta_time),
+                    None => self.world.progress(),
+                };
                 window_e.get::<&mut Window>(|w| w.request_redraw());
             }
             WindowEvent::CloseRequested => event_loop.exit(),
@@ -198,8 +202,12 @@ impl ApplicationHandler<()> for Application {
         };
 
         window_e.get::<&mut Cursor>(|cursor| cursor.process_event(&event));
-        self.world
+        let input_event = self
+            .world
             .get::<&mut Input>(|input| input.process_event(&event));
+        if let Some(input_event) = input_event {
+            input_event.emit(window_e);
+        }
     }
 }
 
@@ -210,6 +218,11 @@ impl Module for ApplicationModule {
     fn module(world: &World) {
         world.module::<Self>("module");
 
+        world
+            .component::<InputCapture>()
+            .add::<flecs::pipeline::Phase>()
+            .depends_on::<flecs::pipeline::OnLoad>();
+
         system!("clear_input", world, &mut Input($))
             .kind::<flecs::pipeline::OnStore>()
             .each(|input| {
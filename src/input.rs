@@ -1,12 +1,13 @@
 use std::collections::HashSet;
 
+use deref_derive::Deref;
 use flecs_ecs::prelude::*;
 use winit::{
     event::{ElementState, MouseButton, MouseScrollDelta, WindowEvent},
     keyboard::{KeyCode, PhysicalKey},
 };
 
-#[derive(Hash, Eq, PartialEq, Debug)]
+#[derive(Hash, Eq, PartialEq, Clone, Copy, Debug)]
 pub enum Button {
     Key(PhysicalKey),
     Mouse(MouseButton),
@@ -30,6 +31,72 @@ impl From<MouseButton> for Button {
     }
 }
 
+// Input events are emitted for the `Cursor` of the window entity that received
+// them, e.g. `observer!(world, KeyDown, &Cursor)`, which headless worlds can have too
+#[derive(Component, Deref)]
+pub struct KeyDown(PhysicalKey);
+
+#[derive(Component, Deref)]
+pub struct KeyUp(PhysicalKey);
+
+#[derive(Component, Deref)]
+pub struct MouseDown(MouseButton);
+
+#[derive(Component, Deref)]
+pub struct MouseUp(MouseButton);
+
+// Scroll delta in lines
+#[derive(Component)]
+pub struct Scroll {
+    pub x: f64,
+    pub y: f64,
+}
+
+pub(crate) enum InputEvent {
+    KeyDown(PhysicalKey),
+    KeyUp(PhysicalKey),
+    MouseDown(MouseButton),
+    MouseUp(MouseButton),
+    Scroll(f64, f64),
+}
+
+impl InputEvent {
+    fn pressed(button: Button) -> Self {
+        match button {
+            Button::Key(key) => InputEvent::KeyDown(key),
+            Button::Mouse(button) => InputEvent::MouseDown(button),
+        }
+    }
+
+    fn released(button: Button) -> Self {
+        match button {
+            Button::Key(key) => InputEvent::KeyUp(key),
+            Button::Mouse(button) => InputEvent::MouseUp(button),
+        }
+    }
+
+    pub(crate) fn emit(self, window: EntityView) {
+        if !window.has::<Cursor>() {
+            return;
+        }
+        let world = window.world();
+        let mut event = world.event();
+        event.add::<Cursor>().entity(window);
+        match self {
+            InputEvent::KeyDown(key) => event.emit(&KeyDown(key)),
+            InputEvent::KeyUp(key) => event.emit(&KeyUp(key)),
+            InputEvent::MouseDown(button) => event.emit(&MouseDown(button)),
+            InputEvent::MouseUp(button) => event.emit(&MouseUp(button)),
+            InputEvent::Scroll(x, y) => event.emit(&Scroll { x, y }),
+        }
+    }
+}
+
+// Phase for higher priority input consumers such as UI layers or overlays, runs
+// before picking and regular systems so they see consumed input as unpressed
+#[derive(Component)]
+pub struct InputCapture;
+
 // Input singleton that tracks button presses and scrolling
 #[derive(Component, Default, Debug)]
 pub struct Input {
@@ -38,88 +105,224 @@ pub struct Input {
     just_released: HashSet<Button>,
     scroll_x: f64,
     scroll_y: f64,
+    text: String,
+    consumed: HashSet<Button>,
+    scroll_consumed: bool,
+    pointer_consumed: bool,
+    // Layer of the scene the topmost shape under the pointer is in, set by picking
+    pointer_layer: Option<i32>,
 }
 
 impl Input {
-    pub(crate) fn process_event(&mut self, event: &WindowEvent) {
+    pub(crate) fn process_event(&mut self, event: &WindowEvent) -> Option<InputEvent> {
         match event {
             WindowEvent::KeyboardInput { event, .. } => match event.state {
                 ElementState::Pressed => {
-                    self.just_pressed.insert(event.physical_key.into());
-                    self.pressed.insert(event.physical_key.into());
-                }
-                ElementState::Released => {
-                    self.pressed.remove(&event.physical_key.into());
-                    self.just_released.insert(event.physical_key.into());
-                }
-            },
-            WindowEvent::MouseInput { state, button, .. } => {
-                let button = *button;
-                match state {
-                    ElementState::Pressed => {
-                        self.just_pressed.insert(button.into());
-                        self.pressed.insert(button.into());
-                    }
-                    ElementState::Released => {
-                        self.pressed.remove(&button.into());
-                        self.just_released.insert(button.into());
+                    if let Some(text) = &event.text {
+                        self.push_text(text);
                     }
+                    Some(self.press(event.physical_key.into()))
                 }
-            }
+                ElementState::Released => Some(self.release(event.physical_key.into())),
+            },
+            WindowEvent::MouseInput { state, button, .. } => match state {
+                ElementState::Pressed => Some(self.press((*button).into())),
+                ElementState::Released => Some(self.release((*button).into())),
+            },
             WindowEvent::MouseWheel { delta, .. } => match delta {
-                MouseScrollDelta::LineDelta(x, y) => {
-                    self.scroll_x += *x as f64;
-                    self.scroll_y += *y as f64;
-                }
+                MouseScrollDelta::LineDelta(x, y) => Some(self.scroll(*x as f64, *y as f64)),
                 MouseScrollDelta::PixelDelta(pos) => {
                     const PIXELS_PER_LINE: f64 = 20.0;
-                    self.scroll_x += pos.x / PIXELS_PER_LINE;
-                    self.scroll_y += pos.y / PIXELS_PER_LINE;
+                    Some(self.scroll(pos.x / PIXELS_PER_LINE, pos.y / PIXELS_PER_LINE))
                 }
             },
-            _ => {}
+            _ => None,
         }
     }
 
+    fn press(&mut self, button: Button) -> InputEvent {
+        self.just_pressed.insert(button);
+        self.pressed.insert(button);
+        InputEvent::pressed(button)
+    }
+
+    fn release(&mut self, button: Button) -> InputEvent {
+        self.pressed.remove(&button);
+        self.just_released.insert(button);
+        InputEvent::released(button)
+    }
+
+    fn scroll(&mut self, x: f64, y: f64) -> InputEvent {
+        self.scroll_x += x;
+        self.scroll_y += y;
+        InputEvent::Scroll(x, y)
+    }
+
+    fn push_text(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
     pub(crate) fn clear_frame(&mut self) {
         self.just_pressed.clear();
         self.just_released.clear();
         self.scroll_x = 0.0;
         self.scroll_y = 0.0;
+        self.text.clear();
+        self.consumed.clear();
+        self.scroll_consumed = false;
+        self.pointer_consumed = false;
+        self.pointer_layer = None;
+    }
+
+    // Consumed input reads as unpressed for every system after this one this frame
+    pub fn consume(&mut self, button: impl Into<Button>) {
+        self.consumed.insert(button.into());
+    }
+
+    pub fn consume_scroll(&mut self) {
+        self.scroll_consumed = true;
+    }
+
+    pub fn consume_pointer(&mut self) {
+        self.pointer_consumed = true;
+    }
+
+    pub fn pointer_consumed(&self) -> bool {
+        self.pointer_consumed
+    }
+
+    pub(crate) fn capture_pointer(&mut self, layer: i32) {
+        self.pointer_layer = Some(self.pointer_layer.map_or(layer, |current| current.max(layer)));
+    }
+
+    pub fn pointer_layer(&self) -> Option<i32> {
+        self.pointer_layer
+    }
+
+    // Input as seen by the scenes on `layer`, see `LayerInput`
+    pub fn layer(&self, layer: i32) -> LayerInput<'_> {
+        LayerInput {
+            input: self,
+            covered: self.pointer_layer.is_some_and(|top| layer < top),
+        }
+    }
+
+    pub fn pressed(&self, button: impl Into<Button>) -> bool {
+        let button = button.into();
+        self.pressed.contains(&button) && !self.consumed.contains(&button)
+    }
+
+    pub fn just_pressed(&self, button: impl Into<Button>) -> bool {
+        let button = button.into();
+        self.just_pressed.contains(&button) && !self.consumed.contains(&button)
+    }
+
+    pub fn just_released(&self, button: impl Into<Button>) -> bool {
+        let button = button.into();
+        self.just_released.contains(&button) && !self.consumed.contains(&button)
+    }
+
+    pub fn scroll_x(&self) -> f64 {
+        if self.scroll_consumed {
+            0.0
+        } else {
+            self.scroll_x
+        }
+    }
+
+    pub fn scroll_y(&self) -> f64 {
+        if self.scroll_consumed {
+            0.0
+        } else {
+            self.scroll_y
+        }
+    }
+
+    // Text typed this frame
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+// Input for the systems of scenes on one layer. While the pointer is over a shape on a
+// higher layer, mouse presses, scrolling and the pointer are consumed for this layer.
+// Releases and held buttons still come through, so drags started on this layer can end
+// over a higher one.
+#[derive(Clone, Copy)]
+pub struct LayerInput<'a> {
+    input: &'a Input,
+    covered: bool,
+}
+
+impl LayerInput<'_> {
+    pub fn pointer_consumed(&self) -> bool {
+        self.covered || self.input.pointer_consumed()
     }
 
     pub fn pressed(&self, button: impl Into<Button>) -> bool {
-        self.pressed.contains(&button.into())
+        self.input.pressed(button)
     }
 
     pub fn just_pressed(&self, button: impl Into<Button>) -> bool {
-        self.just_pressed.contains(&button.into())
+        let button = button.into();
+        !(self.covered && matches!(button, Button::Mouse(_))) && self.input.just_pressed(button)
     }
 
     pub fn just_released(&self, button: impl Into<Button>) -> bool {
-        self.just_released.contains(&button.into())
+        self.input.just_released(button)
     }
 
     pub fn scroll_x(&self) -> f64 {
-        self.scroll_x
+        if self.covered {
+            0.0
+        } else {
+            self.input.scroll_x()
+        }
     }
 
     pub fn scroll_y(&self) -> f64 {
-        self.scroll_y
+        if self.covered {
+            0.0
+        } else {
+            self.input.scroll_y()
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        self.input.text()
     }
 }
 
 // Tracks cursor position per window
-#[derive(Component, Default, Clone)]
+#[derive(Component, Clone)]
 pub struct Cursor {
     x: f64,
     y: f64,
     in_frame: bool,
+    scale_factor: f64,
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Self::new(1.0)
+    }
 }
 
 impl Cursor {
+    pub(crate) fn new(scale_factor: f64) -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            in_frame: false,
+            scale_factor,
+        }
+    }
+
     pub(crate) fn process_event(&mut self, event: &WindowEvent) {
         match event {
+            WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+                self.scale_factor = *scale_factor;
+            }
             WindowEvent::CursorEntered { .. } => {
                 self.in_frame = true;
             }
@@ -127,13 +330,17 @@ impl Cursor {
                 self.in_frame = false;
             }
             WindowEvent::CursorMoved { position, .. } => {
-                self.x = position.x;
-                self.y = position.y;
+                self.move_to(position.x, position.y);
             }
             _ => {}
         }
     }
 
+    fn move_to(&mut self, x: f64, y: f64) {
+        self.x = x;
+        self.y = y;
+    }
+
     pub fn x(&self) -> f64 {
         self.x
     }
@@ -145,4 +352,69 @@ impl Cursor {
     pub fn in_frame(&self) -> bool {
         self.in_frame
     }
+
+    // Scale factor of the window, positions are in physical pixels
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+}
+
+// Feeds input to the same state machines as winit events, so tests can drive a world
+// frame by frame with `world.progress()`. The window entity only needs a `Cursor`, and
+// the world needs `world.set(Input::default())` and `world.import::<ApplicationModule>()`
+// so that `clear_input` ends each frame's presses.
+pub struct SyntheticInput<'a> {
+    window: EntityView<'a>,
+}
+
+impl<'a> SyntheticInput<'a> {
+    pub fn new(window: EntityView<'a>) -> Self {
+        Self { window }
+    }
+
+    pub fn press(&self, button: impl Into<Button>) -> &Self {
+        self.input(|input| Some(input.press(button.into())))
+    }
+
+    pub fn release(&self, button: impl Into<Button>) -> &Self {
+        self.input(|input| Some(input.release(button.into())))
+    }
+
+    pub fn click(&self, button: impl Into<Button>) -> &Self {
+        let button = button.into();
+        self.press(button).release(button)
+    }
+
+    pub fn scroll(&self, dx: f64, dy: f64) -> &Self {
+        self.input(|input| Some(input.scroll(dx, dy)))
+    }
+
+    pub fn type_text(&self, text: &str) -> &Self {
+        self.input(|input| {
+            input.push_text(text);
+            None
+        })
+    }
+
+    // Moves the cursor to a position in physical pixels, entering the window
+    pub fn move_cursor(&self, x: f64, y: f64) -> &Self {
+        self.window.get::<&mut Cursor>(|cursor| {
+            cursor.in_frame = true;
+            cursor.move_to(x, y);
+        });
+        self
+    }
+
+    pub fn leave(&self) -> &Self {
+        self.window.get::<&mut Cursor>(|cursor| cursor.in_frame = false);
+        self
+    }
+
+    fn input(&self, f: impl FnOnce(&mut Input) -> Option<InputEvent>) -> &Self {
+        let event = self.window.world().get::<&mut Input>(f);
+        if let Some(event) = event {
+            event.emit(self.window);
+        }
+        self
+    }
 }
@@ -1,13 +1,25 @@
 mod application;
+mod blit;
 mod input;
+mod picking;
+mod post;
+mod recording;
 mod render;
+mod render_thread;
+mod screenshot;
 mod shape;
+mod svg;
 mod text;
 mod window;
 
 pub use application::*;
 pub use input::*;
+pub use picking::*;
+pub use post::*;
+pub use recording::*;
 pub use render::*;
+pub use screenshot::*;
 pub use shape::*;
+pub use svg::*;
 pub use text::*;
 pub use window::*;
@@ -0,0 +1,161 @@
+use flecs_ecs::prelude::*;
+use winit::event::MouseButton;
+
+use crate::{
+    shape::{z_index, ShapeTypes},
+    Cursor, GlobalTransform, Input, Transform, VelloScene, Viewport,
+};
+
+// Added to the topmost shape under the cursor
+#[derive(Component)]
+pub struct Hovered;
+
+// Added to the shape the left mouse button went down on, until it is released
+#[derive(Component)]
+pub struct Pressed;
+
+// Pointer events are emitted for the `Transform` of the shape entity, so observers
+// should include it as a term, e.g. `observer!(world, Click, &Transform)`
+#[derive(Component)]
+pub struct PointerEnter;
+
+#[derive(Component)]
+pub struct PointerLeave;
+
+#[derive(Component)]
+pub struct Click;
+
+// Picking singleton that tracks which shape is under the pointer
+#[derive(Component, Default)]
+pub struct Picking {
+    // Layer and composite order of the scene the hit shape is in and its z, with the shape
+    hit: Option<(i32, usize, i32, Entity)>,
+    hovered: Option<Entity>,
+    pressed: Option<Entity>,
+}
+
+impl Picking {
+    pub fn hovered(&self) -> Option<Entity> {
+        self.hovered
+    }
+
+    pub fn pressed(&self) -> Option<Entity> {
+        self.pressed
+    }
+}
+
+// Position of the shape's scene among the scenes of its target, scenes on the same layer
+// are composited in this order
+fn composite_order(shape: EntityView) -> usize {
+    let mut scene = shape;
+    while !scene.has::<VelloScene>() {
+        match scene.parent() {
+            Some(parent) => scene = parent,
+            None => return 0,
+        }
+    }
+    let Some(target) = scene.parent() else {
+        return 0;
+    };
+    let mut order = 0;
+    let mut found = false;
+    target.each_child(|child| {
+        if found || child.id() == scene.id() {
+            found = true;
+        } else if child.has::<VelloScene>() {
+            order += 1;
+        }
+    });
+    order
+}
+
+#[derive(Component)]
+pub struct PickingModule;
+
+impl Module for PickingModule {
+    fn module(world: &World) {
+        world.module::<Self>("module");
+
+        world.set(Picking::default());
+
+        // Hit tests every shape type against the cursor, keeping the hit that is painted
+        // on top
+        system!("pick_shapes", world, &mut Picking($), &Input($), &ShapeTypes($), &Cursor(up), &VelloScene(up), ?&Viewport(up), &GlobalTransform)
+            .kind::<flecs::pipeline::PreUpdate>()
+            .each_entity(|e, (picking, input, types, cursor, scene, viewport, transform)| {
+                if !cursor.in_frame() || input.pointer_consumed() {
+                    return;
+                }
+                let Some(position) = scene.cursor_to_world(cursor, viewport) else {
+                    return;
+                };
+                let point = transform.inverse() * position;
+                if !types.hit_tests.iter().any(|hit_test| hit_test(e, point)) {
+                    return;
+                }
+                // Ordered the same way scenes are composited and shapes are painted
+                let key = (scene.layer, composite_order(e), z_index(e), e.id());
+                if !picking.hit.is_some_and(|hit| key < hit) {
+                    picking.hit = Some(key);
+                }
+            });
+
+        // The layer of the topmost hit takes the pointer from the layers below it
+        system!("update_pointer", world, &mut Picking($), &mut Input($))
+            .kind::<flecs::pipeline::PreUpdate>()
+            .each_iter(|it, _, (picking, input)| {
+                let world = it.world();
+                let hit = picking.hit.take().map(|(layer, _, _, hit)| {
+                    input.capture_pointer(layer);
+                    hit
+                });
+
+                if hit != picking.hovered {
+                    if let Some(previous) = picking.hovered {
+                        let previous = previous.entity_view(world);
+                        if previous.is_alive() {
+                            previous.remove::<Hovered>();
+                            world
+                                .event()
+                                .add::<Transform>()
+                                .entity(previous)
+                                .emit(&PointerLeave);
+                        }
+                    }
+                    if let Some(current) = hit {
+                        let current = current.entity_view(world);
+                        current.add::<Hovered>();
+                        world
+                            .event()
+                            .add::<Transform>()
+                            .entity(current)
+                            .emit(&PointerEnter);
+                    }
+                    picking.hovered = hit;
+                }
+
+                if input.just_pressed(MouseButton::Left) {
+                    if let Some(hovered) = picking.hovered {
+                        hovered.entity_view(world).add::<Pressed>();
+                        picking.pressed = Some(hovered);
+                    }
+                }
+
+                if input.just_released(MouseButton::Left) {
+                    if let Some(pressed) = picking.pressed.take() {
+                        let pressed_e = pressed.entity_view(world);
+                        if pressed_e.is_alive() {
+                            pressed_e.remove::<Pressed>();
+                            if picking.hovered == Some(pressed) {
+                                world
+                                    .event()
+                                    .add::<Transform>()
+                                    .entity(pressed_e)
+                                    .emit(&Click);
+                            }
+                        }
+                    }
+                }
+            });
+    }
+}
@@ -0,0 +1,246 @@
+use flecs_ecs::prelude::*;
+use vello::peniko::Color;
+use wgpu::{util::DeviceExt, Device, Queue, RenderPipeline, Texture, TextureView};
+
+use crate::RenderTarget;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Effect {
+    // Adds a glow around colors brighter than `threshold`, `radius` is in pixels
+    Bloom {
+        threshold: f32,
+        intensity: f32,
+        radius: f32,
+    },
+    // Darkens towards the corners, starting at `radius` as a fraction of the half diagonal
+    Vignette { strength: f32, radius: f32 },
+    // Gaussian blur, `radius` is in pixels and capped at 64
+    Blur { radius: f32 },
+    // `exposure` is in stops, the others scale around their neutral value of 1
+    ColorGrade {
+        exposure: f32,
+        contrast: f32,
+        saturation: f32,
+        tint: Color,
+    },
+}
+
+// Effects applied in order to what vello rendered for a window or headless target,
+// before it is blitted, captured or recorded. Scenes are composited before this runs,
+// so the chain applies to all of the target's scenes. Set the component again to
+// change it, which renders the target again.
+#[derive(Component, Clone, Default, Debug)]
+pub struct PostProcess {
+    pub effects: Vec<Effect>,
+}
+
+impl PostProcess {
+    pub fn new(effects: impl IntoIterator<Item = Effect>) -> Self {
+        Self {
+            effects: effects.into_iter().collect(),
+        }
+    }
+}
+
+// Index into `PostProcessor::pipelines` and the pass parameters
+type Pass = (usize, [f32; 8]);
+
+fn passes(effect: &Effect) -> Vec<Pass> {
+    match *effect {
+        Effect::Bloom {
+            threshold,
+            intensity,
+            radius,
+        } => vec![(0, [threshold, intensity, radius, 0.0, 0.0, 0.0, 0.0, 0.0])],
+        Effect::Vignette { strength, radius } => {
+            vec![(1, [strength, radius, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0])]
+        }
+        Effect::Blur { radius } => {
+            let radius = radius.clamp(0.0, 64.0);
+            // Separable, so horizontally then vertically
+            vec![
+                (2, [radius, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0]),
+                (2, [radius, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0]),
+            ]
+        }
+        Effect::ColorGrade {
+            exposure,
+            contrast,
+            saturation,
+            tint,
+        } => {
+            let [r, g, b] = [tint.r, tint.g, tint.b].map(|channel| channel as f32 / 255.0);
+            vec![(3, [exposure, contrast, saturation, 0.0, r, g, b, 1.0])]
+        }
+    }
+}
+
+// Runs effect chains in place on render targets through a scratch texture
+#[derive(Component)]
+pub(crate) struct PostProcessor {
+    bind_group_layout: wgpu::BindGroupLayout,
+    pipelines: Vec<RenderPipeline>,
+    // Recreated whenever a target of another size is processed, so resizing does not
+    // keep a texture around for every size it passed through
+    scratch: Option<(Texture, TextureView)>,
+}
+
+impl PostProcessor {
+    pub(crate) fn new(device: &Device) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("post"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/post.wgsl").into()),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        // In the order of the indices used by `passes`
+        let pipelines = ["fs_bloom", "fs_vignette", "fs_blur", "fs_color_grade"]
+            .into_iter()
+            .map(|entry_point| {
+                device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some(entry_point),
+                    layout: Some(&pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        compilation_options: Default::default(),
+                        buffers: &[],
+                    },
+                    primitive: wgpu::PrimitiveState::default(),
+                    depth_stencil: None,
+                    multisample: wgpu::MultisampleState::default(),
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point,
+                        compilation_options: Default::default(),
+                        targets: &[Some(RenderTarget::FORMAT.into())],
+                    }),
+                    multiview: None,
+                    cache: None,
+                })
+            })
+            .collect();
+
+        Self {
+            bind_group_layout,
+            pipelines,
+            scratch: None,
+        }
+    }
+
+    pub(crate) fn apply(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        texture: &Texture,
+        view: &TextureView,
+        effects: &[Effect],
+    ) {
+        if effects.is_empty() {
+            return;
+        }
+        if self
+            .scratch
+            .as_ref()
+            .is_some_and(|(scratch, _)| scratch.size() != texture.size())
+        {
+            self.scratch = None;
+        }
+        let (scratch, scratch_view) = self.scratch.get_or_insert_with(|| {
+            let scratch = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("post_scratch"),
+                size: texture.size(),
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: RenderTarget::FORMAT,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                view_formats: &[],
+            });
+            let view = scratch.create_view(&wgpu::TextureViewDescriptor::default());
+            (scratch, view)
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("post"),
+        });
+        for (pipeline, params) in effects.iter().flat_map(passes) {
+            let uniforms = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("post"),
+                contents: &params.map(f32::to_ne_bytes).concat(),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("post"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: uniforms.as_entire_binding(),
+                    },
+                ],
+            });
+            {
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("post"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: scratch_view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            store: wgpu::StoreOp::Store,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                pass.set_pipeline(&self.pipelines[pipeline]);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.draw(0..3, 0..1);
+            }
+            // The next pass reads the result from the target again
+            encoder.copy_texture_to_texture(
+                scratch.as_image_copy(),
+                texture.as_image_copy(),
+                texture.size(),
+            );
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+}
@@ -0,0 +1,155 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Sender},
+};
+
+use flecs_ecs::prelude::*;
+
+use crate::screenshot::{write_png, ReadbackTarget};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecordingFormat {
+    // Numbered `frame_000000.png` files
+    Png,
+    // A single uncompressed `recording.y4m` stream in 4:4:4 YCbCr
+    Y4m,
+}
+
+// Add to a window or headless target to write every frame it renders to `dir`. While a
+// recording is active the simulation advances by a fixed `1 / fps` seconds per frame,
+// so the output does not depend on how long rendering and readback take. `Application`
+// applies the step itself, worlds driven by hand should advance with
+// `world.progress_time(fixed_step(&world).unwrap_or(delta))` to record deterministically.
+#[derive(Component)]
+pub struct Recording {
+    pub dir: PathBuf,
+    pub format: RecordingFormat,
+    pub fps: u32,
+    frame: u64,
+    stream: Option<FrameSender>,
+}
+
+impl Recording {
+    pub fn new(dir: impl Into<PathBuf>, format: RecordingFormat, fps: u32) -> Self {
+        Self {
+            dir: dir.into(),
+            format,
+            fps: fps.max(1),
+            frame: 0,
+            stream: None,
+        }
+    }
+
+    pub fn png(dir: impl Into<PathBuf>, fps: u32) -> Self {
+        Self::new(dir, RecordingFormat::Png, fps)
+    }
+
+    pub fn y4m(dir: impl Into<PathBuf>, fps: u32) -> Self {
+        Self::new(dir, RecordingFormat::Y4m, fps)
+    }
+
+    // Frames captured so far
+    pub fn frames(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn step(&self) -> f32 {
+        1.0 / self.fps as f32
+    }
+
+    // Where the next frame should be written
+    pub(crate) fn next_frame(
+        &mut self,
+        width: u32,
+        height: u32,
+    ) -> std::io::Result<ReadbackTarget> {
+        if self.frame == 0 {
+            std::fs::create_dir_all(&self.dir)?;
+        }
+        let stream = match &self.stream {
+            Some(stream) => stream.clone(),
+            None => {
+                let stream = match self.format {
+                    RecordingFormat::Png => spawn_png(&self.dir),
+                    RecordingFormat::Y4m => {
+                        let path = self.dir.join("recording.y4m");
+                        spawn_y4m(&path, width, height, self.fps)?
+                    }
+                };
+                self.stream.insert(stream).clone()
+            }
+        };
+        self.frame += 1;
+        Ok(ReadbackTarget::Stream(stream))
+    }
+}
+
+// Simulation step while a recording is active, set by the recording observers
+#[derive(Component)]
+pub(crate) struct FixedStep(pub(crate) f32);
+
+// Seconds each frame should advance the simulation by while a recording is active
+pub fn fixed_step(world: &World) -> Option<f32> {
+    world.try_get::<&FixedStep>(|step| step.0)
+}
+
+// Width, height and RGBA data of a frame read back for a recording
+pub(crate) type FrameSender = Sender<(u32, u32, Vec<u8>)>;
+
+// Writes numbered frames on one thread, in the order they were read back. Like the Y4M
+// stream it ends once every sender is dropped.
+fn spawn_png(dir: &Path) -> FrameSender {
+    let (sender, receiver) = mpsc::channel::<(u32, u32, Vec<u8>)>();
+    let dir = dir.to_path_buf();
+    std::thread::spawn(move || {
+        for (frame, (width, height, data)) in receiver.into_iter().enumerate() {
+            let path = dir.join(format!("frame_{frame:06}.png"));
+            if let Err(err) = write_png(&path, width, height, &data) {
+                eprintln!("Failed to write {}: {err}", path.display());
+            }
+        }
+    });
+    sender
+}
+
+// Writes frames on their own thread, in the order they were read back. The stream ends
+// once every sender is dropped.
+fn spawn_y4m(path: &Path, width: u32, height: u32, fps: u32) -> std::io::Result<FrameSender> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "YUV4MPEG2 W{width} H{height} F{fps}:1 Ip A1:1 C444")?;
+
+    let (sender, receiver) = mpsc::channel::<(u32, u32, Vec<u8>)>();
+    let path = path.to_path_buf();
+    std::thread::spawn(move || {
+        for (frame_width, frame_height, data) in receiver {
+            if (frame_width, frame_height) != (width, height) {
+                eprintln!("Frame size changed while recording {}, frame skipped.", path.display());
+                continue;
+            }
+            if let Err(err) = write_y4m_frame(&mut file, &data) {
+                eprintln!("Failed to write {}: {err}", path.display());
+                return;
+            }
+        }
+    });
+    Ok(sender)
+}
+
+fn write_y4m_frame(file: &mut impl Write, rgba: &[u8]) -> std::io::Result<()> {
+    let pixels = rgba.len() / 4;
+    let mut planes = vec![0; pixels * 3];
+    let (y, chroma) = planes.split_at_mut(pixels);
+    let (cb, cr) = chroma.split_at_mut(pixels);
+    // BT.601 in studio range, which is what players assume without a color range tag
+    for (i, pixel) in rgba.chunks_exact(4).enumerate() {
+        let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|channel| channel as f32 / 255.0);
+        y[i] = (16.0 + 65.481 * r + 128.553 * g + 24.966 * b).round() as u8;
+        cb[i] = (128.0 - 37.797 * r - 74.203 * g + 112.0 * b).round() as u8;
+        cr[i] = (128.0 + 112.0 * r - 93.786 * g - 18.214 * b).round() as u8;
+    }
+    file.write_all(b"FRAME\n")?;
+    file.write_all(&planes)?;
+    file.flush()
+}
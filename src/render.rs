@@ -1,49 +1,337 @@
 use deref_derive::{Deref, DerefMut};
 use flecs_ecs::prelude::*;
-use std::{num::NonZeroUsize, sync::Mutex};
-use wgpu::{Adapter, Device, Instance, Queue, TextureFormat};
+use std::{
+    mem::size_of_val,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+use wgpu::{Adapter, Device, Instance, Queue, Surface, TextureFormat};
 
-use crate::{application::Resize, window::Window};
+use crate::{
+    application::Resize,
+    blit::Blitter,
+    input::Cursor,
+    post::{PostProcess, PostProcessor},
+    render_thread::{RenderCommand, RenderThread},
+    recording::{FixedStep, Recording},
+    screenshot::{Readback, ReadbackTarget, Readbacks},
+    shape::DrawItem,
+    text::TextWriter,
+    window::{SurfaceSettings, Window},
+    Screenshot,
+};
+
+// Which adapter and rasterization path to use, set as a singleton before the
+// application initializes. `Auto` prefers hardware and falls back to software.
+#[derive(Component, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum RenderBackend {
+    #[default]
+    Auto,
+    Hardware,
+    Fallback,
+}
+
+// Where frames are rendered, set as a singleton before the application initializes.
+// `Pipelined` hands each frame's scenes to a render thread that renders and presents
+// them while the next frame is simulated. In that mode the window's frame is never
+// acquired on the main thread, so `Window::texture` is always `None` and custom passes
+// cannot draw into it.
+#[derive(Component, Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum RenderMode {
+    #[default]
+    Direct,
+    Pipelined,
+}
 
 #[derive(Component)]
 pub struct WGPU {
     pub adapter: Adapter,
-    pub device: Device,
+    pub device: Arc<Device>,
     pub instance: Instance,
-    pub queue: Queue,
-    pub format: TextureFormat,
+    pub queue: Arc<Queue>,
+    // The backend actually in use, either `Hardware` or `Fallback`
+    pub backend: RenderBackend,
+    lost: Arc<AtomicBool>,
+}
+
+impl WGPU {
+    pub async fn new(
+        instance: Instance,
+        surface: Option<&Surface<'_>>,
+        backend: RenderBackend,
+    ) -> Self {
+        let (adapter, device, queue, backend) = Self::request(&instance, surface, backend).await;
+        let lost = Arc::new(AtomicBool::new(false));
+        watch_device(&device, lost.clone());
+        Self {
+            adapter,
+            device: Arc::new(device),
+            instance,
+            queue: Arc::new(queue),
+            backend,
+            lost,
+        }
+    }
+
+    // Whether the device was lost, it is recreated at the start of the next frame
+    pub fn is_lost(&self) -> bool {
+        self.lost.load(Ordering::Acquire)
+    }
+
+    // Requests a new adapter and device from the same instance
+    pub async fn recreate(&mut self, surface: Option<&Surface<'_>>, backend: RenderBackend) {
+        let (adapter, device, queue, backend) =
+            Self::request(&self.instance, surface, backend).await;
+        let lost = Arc::new(AtomicBool::new(false));
+        watch_device(&device, lost.clone());
+        self.adapter = adapter;
+        self.device = Arc::new(device);
+        self.queue = Arc::new(queue);
+        self.backend = backend;
+        self.lost = lost;
+    }
+
+    async fn request(
+        instance: &Instance,
+        surface: Option<&Surface<'_>>,
+        backend: RenderBackend,
+    ) -> (Adapter, Device, Queue, RenderBackend) {
+        let options = |force_fallback_adapter| wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter,
+            compatible_surface: surface,
+        };
+        let adapter = match backend {
+            RenderBackend::Hardware => instance.request_adapter(&options(false)).await,
+            RenderBackend::Fallback => instance.request_adapter(&options(true)).await,
+            RenderBackend::Auto => match instance.request_adapter(&options(false)).await {
+                Some(adapter) => Some(adapter),
+                None => instance.request_adapter(&options(true)).await,
+            },
+        }
+        .expect("Failed to find an appropriate adapter");
+
+        // Software rasterizers such as llvmpipe can be handed out as regular adapters
+        let backend = match adapter.get_info().device_type {
+            wgpu::DeviceType::Cpu => RenderBackend::Fallback,
+            _ if backend == RenderBackend::Fallback => RenderBackend::Fallback,
+            _ => RenderBackend::Hardware,
+        };
+
+        // Create the logical device and command queue
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    required_features: wgpu::Features::empty(),
+                    required_limits: wgpu::Limits::default().using_resolution(adapter.limits()),
+                    memory_hints: Default::default(),
+                },
+                None,
+            )
+            .await
+            .expect("Failed to create device");
+
+        (adapter, device, queue, backend)
+    }
+}
+
+fn watch_device(device: &Device, lost: Arc<AtomicBool>) {
+    device.set_device_lost_callback(move |reason, message| {
+        // Dropping the device on purpose is not a loss
+        if matches!(
+            reason,
+            wgpu::DeviceLostReason::Dropped | wgpu::DeviceLostReason::ReplacedCallback
+        ) {
+            return;
+        }
+        eprintln!("Device lost ({reason:?}): {message}");
+        lost.store(true, Ordering::Release);
+    });
+}
+
+// Emitted for the `WGPU` singleton once the device has been recreated after it was lost,
+// so that modules can rebuild their own GPU resources, e.g.
+// `observer!(world, DeviceRecreated, &WGPU)`
+#[derive(Component)]
+pub struct DeviceRecreated;
+
+// The vello renderer, which only builds pipelines for the antialiasing modes it was
+// created with, so it is recreated the first time a scene asks for another one
+pub(crate) struct Rasterizer {
+    renderer: vello::Renderer,
+    support: vello::AaSupport,
+    use_cpu: bool,
+}
+
+impl Rasterizer {
+    pub(crate) fn new(device: &Device, backend: RenderBackend) -> Self {
+        let support = vello::AaSupport::area_only();
+        let use_cpu = backend == RenderBackend::Fallback;
+        Self {
+            renderer: Self::renderer(device, use_cpu, support),
+            support,
+            use_cpu,
+        }
+    }
+
+    fn renderer(device: &Device, use_cpu: bool, support: vello::AaSupport) -> vello::Renderer {
+        vello::Renderer::new(
+            device,
+            vello::RendererOptions {
+                // Scenes are rendered to a `RenderTarget` and blitted to surfaces
+                surface_format: None,
+                use_cpu,
+                antialiasing_support: support,
+                num_init_threads: NonZeroUsize::new(1),
+            },
+        )
+        .expect("Failed to create vello renderer.")
+    }
+
+    fn support(&mut self, device: &Device, antialiasing: vello::AaConfig) {
+        let supported = match antialiasing {
+            vello::AaConfig::Area => self.support.area,
+            vello::AaConfig::Msaa8 => self.support.msaa8,
+            vello::AaConfig::Msaa16 => self.support.msaa16,
+        };
+        if supported {
+            return;
+        }
+        self.support = vello::AaSupport {
+            area: self.support.area || matches!(antialiasing, vello::AaConfig::Area),
+            msaa8: self.support.msaa8 || matches!(antialiasing, vello::AaConfig::Msaa8),
+            msaa16: self.support.msaa16 || matches!(antialiasing, vello::AaConfig::Msaa16),
+        };
+        self.renderer = Self::renderer(device, self.use_cpu, self.support);
+    }
+
+    pub(crate) fn render(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &vello::Scene,
+        view: &wgpu::TextureView,
+        params: &vello::RenderParams,
+    ) {
+        self.support(device, params.antialiasing_method);
+        self.renderer
+            .render_to_texture(device, queue, scene, view, params)
+            .expect("Failed to render scene.");
+    }
 }
 
 #[derive(Component)]
 pub struct Vello {
     // The `Mutex` makes this `Sync` (it is already `Send`) so that we
-    // can store it as a component.
-    renderer: Mutex<vello::Renderer>,
+    // can store it as a component. Taken by the render thread in pipelined mode.
+    rasterizer: Option<Mutex<Rasterizer>>,
+    // Scratch scene that a window's layers are appended into
+    composite: vello::Scene,
 }
 
 impl Vello {
     pub fn new(wgpu: &mut WGPU) -> Self {
         Self {
-            renderer: Mutex::new(
-                vello::Renderer::new(
-                    &wgpu.device,
-                    vello::RendererOptions {
-                        surface_format: Some(wgpu.format),
-                        use_cpu: false,
-                        antialiasing_support: vello::AaSupport::area_only(),
-                        num_init_threads: NonZeroUsize::new(1),
-                    },
-                )
-                .expect("Failed to create vello renderer."),
-            ),
+            rasterizer: Some(Mutex::new(Rasterizer::new(&wgpu.device, wgpu.backend))),
+            composite: vello::Scene::new(),
         }
     }
 }
 
-#[derive(Component, Deref, DerefMut)]
+// Texture vello renders a window's scenes into before they are blitted to its
+// surface, so that surfaces can use formats vello cannot render to directly. An entity
+// with a target and no window is a headless target, its child scenes are rendered into
+// it every frame they change, e.g. for recordings.
+#[derive(Component)]
+pub struct RenderTarget {
+    texture: Arc<wgpu::Texture>,
+    view: Arc<wgpu::TextureView>,
+    // Whether the texture is missing the window's latest composite
+    pub(crate) stale: bool,
+    // Scenes composited into the texture, bottom to top
+    pub(crate) scenes: Vec<Entity>,
+}
+
+impl RenderTarget {
+    pub const FORMAT: TextureFormat = TextureFormat::Rgba8Unorm;
+
+    pub fn new(device: &Device, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("render_target"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            texture: Arc::new(texture),
+            view: Arc::new(view),
+            stale: true,
+            scenes: Vec::new(),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.texture.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.texture.height()
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+}
+
+// Antialiasing for everything a window or headless target renders, its scenes are
+// rendered in one pass so they share it. Area antialiasing without one.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Antialiasing(pub vello::AaConfig);
+
+// Scenes that are children of a window are drawn bottom to top by `layer`, only the
+// bottom layer's `base_color` applies and the rest composite on top.
+// A scene keeps its encoding between frames and is only rendered again once something
+// in it changes. Drawing into it directly replaces what it retained from the last frame,
+// changes to `base_color` or `layer` need a `mark_dirty`.
+#[derive(Component)]
 pub struct VelloScene {
-    #[deref]
     scene: vello::Scene,
+    // Whether the window needs to render the scene again
+    pub(crate) dirty: bool,
+    // Whether `scene` holds an encoding that has already been rendered
+    pub(crate) retained: bool,
+    // Camera the shapes were encoded with
+    encoded_camera: vello::kurbo::Affine,
+    // What the scene covers of its target, shapes outside it are not encoded
+    pub(crate) visible: Option<vello::kurbo::Rect>,
+    // Shapes in the current encoding and shapes left out of it
+    pub(crate) encoded: usize,
+    pub(crate) culled: usize,
+    // Shapes collected this frame, encoded in paint order before rendering
+    pub(crate) draw_list: Vec<DrawItem>,
+    pub layer: i32,
     pub base_color: vello::peniko::Color,
     pub camera: vello::kurbo::Affine,
     pub scale: f64,
@@ -54,6 +342,14 @@ impl Default for VelloScene {
     fn default() -> Self {
         Self {
             scene: vello::Scene::new(),
+            dirty: true,
+            retained: false,
+            encoded_camera: vello::kurbo::Affine::IDENTITY,
+            visible: None,
+            encoded: 0,
+            culled: 0,
+            draw_list: Vec::new(),
+            layer: 0,
             base_color: vello::peniko::Color::GRAY,
             camera: vello::kurbo::Affine::IDENTITY,
             scale: 1.0,
@@ -62,6 +358,279 @@ impl Default for VelloScene {
     }
 }
 
+impl std::ops::Deref for VelloScene {
+    type Target = vello::Scene;
+
+    fn deref(&self) -> &Self::Target {
+        &self.scene
+    }
+}
+
+impl std::ops::DerefMut for VelloScene {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.invalidate();
+        &mut self.scene
+    }
+}
+
+impl VelloScene {
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    // Shapes left out of the scene's current encoding because they were out of view
+    pub fn culled(&self) -> usize {
+        self.culled
+    }
+
+    // Renders the scene again without re-encoding it
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    // Discards the retained encoding so that shapes are encoded again this frame
+    pub(crate) fn invalidate(&mut self) {
+        if self.retained {
+            self.scene.reset();
+            self.retained = false;
+            self.encoded = 0;
+            self.culled = 0;
+        }
+        self.dirty = true;
+    }
+
+    // Converts a cursor position into the scene's world space, `None` when the cursor
+    // is outside the scene's viewport
+    pub fn cursor_to_world(
+        &self,
+        cursor: &Cursor,
+        viewport: Option<&Viewport>,
+    ) -> Option<vello::kurbo::Point> {
+        let mut position = vello::kurbo::Point::new(cursor.x(), cursor.y());
+        if let Some(viewport) = viewport {
+            let rect = viewport.physical_rect(cursor.scale_factor());
+            if !rect.contains(position) {
+                return None;
+            }
+            position -= rect.origin().to_vec2();
+        }
+        Some(self.camera.inverse() * position)
+    }
+}
+
+// Restricts a scene to a rectangle of its window, its origin becomes the scene's
+// origin. A scene with a viewport paints its `base_color` as the viewport background.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Viewport {
+    pub rect: vello::kurbo::Rect,
+    // Whether `rect` is in logical pixels and should be scaled by the window's scale factor
+    pub logical: bool,
+}
+
+impl Viewport {
+    pub fn physical(rect: vello::kurbo::Rect) -> Self {
+        Self {
+            rect,
+            logical: false,
+        }
+    }
+
+    pub fn logical(rect: vello::kurbo::Rect) -> Self {
+        Self {
+            rect,
+            logical: true,
+        }
+    }
+
+    pub fn physical_rect(&self, scale_factor: f64) -> vello::kurbo::Rect {
+        if self.logical {
+            vello::kurbo::Rect::from_origin_size(
+                (self.rect.x0 * scale_factor, self.rect.y0 * scale_factor),
+                (self.rect.width() * scale_factor, self.rect.height() * scale_factor),
+            )
+        } else {
+            self.rect
+        }
+    }
+}
+
+// Render phases run after `OnStore`, once the window's frame has been acquired.
+// Custom passes can record into the `FrameEncoder` in any of them. Vello's output is
+// blended over what `PreRender` drew into `Window::view`, so it shows through where the
+// bottom scene's `base_color` is transparent, and `PostRender` draws on top.
+#[derive(Component)]
+pub struct PreRender;
+
+// Vello draws each window's scenes at the start of this phase
+#[derive(Component)]
+pub struct Render;
+
+#[derive(Component)]
+pub struct PostRender;
+
+// Frames are submitted, captured and presented
+#[derive(Component)]
+pub struct Present;
+
+fn render_phases(world: &World) {
+    world
+        .component::<PreRender>()
+        .add::<flecs::pipeline::Phase>()
+        .depends_on::<flecs::pipeline::OnStore>();
+    world
+        .component::<Render>()
+        .add::<flecs::pipeline::Phase>()
+        .depends_on::<PreRender>();
+    world
+        .component::<PostRender>()
+        .add::<flecs::pipeline::Phase>()
+        .depends_on::<Render>();
+    world
+        .component::<Present>()
+        .add::<flecs::pipeline::Phase>()
+        .depends_on::<PostRender>();
+}
+
+// Counts window renders, a render is skipped when none of the window's scenes changed
+#[derive(Component, Clone, Copy, Default, Debug)]
+pub struct FrameCounter {
+    pub rendered: u64,
+    pub skipped: u64,
+}
+
+// What the last frame cost, registered with reflection so that it can be charted
+// in the flecs explorer
+#[derive(Component, Clone, Copy, Default, Debug)]
+#[meta]
+pub struct RenderStats {
+    // Shapes encoded this frame, zero while every scene is retained
+    pub shapes_encoded: u32,
+    pub shapes_culled: u32,
+    // Size of every scene's encoding
+    pub encoding_bytes: u64,
+    pub glyph_runs: u32,
+    // CPU time in milliseconds, summed over windows
+    pub create_texture_ms: f32,
+    pub render_ms: f32,
+    pub present_ms: f32,
+}
+
+// Stats for a scene's current encoding, set on every scene each frame
+#[derive(Component, Clone, Copy, Default, Debug)]
+#[meta]
+pub struct SceneStats {
+    pub shapes: u32,
+    pub culled: u32,
+    pub encoding_bytes: u64,
+}
+
+// The frame's stats while they are being collected
+#[derive(Component, Default, Deref, DerefMut)]
+pub(crate) struct FrameStats(RenderStats);
+
+// Adds the time until it is dropped to a stat, so early returns are counted too
+struct Timer<'a> {
+    stat: &'a mut f32,
+    start: Instant,
+}
+
+impl<'a> Timer<'a> {
+    fn new(stat: &'a mut f32) -> Self {
+        Self {
+            stat,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Timer<'_> {
+    fn drop(&mut self) {
+        *self.stat += self.start.elapsed().as_secs_f32() * 1000.0;
+    }
+}
+
+fn encoding_bytes(scene: &vello::Scene) -> u64 {
+    let encoding = scene.encoding();
+    (size_of_val(encoding.path_tags.as_slice())
+        + size_of_val(encoding.path_data.as_slice())
+        + size_of_val(encoding.draw_tags.as_slice())
+        + size_of_val(encoding.draw_data.as_slice())
+        + size_of_val(encoding.transforms.as_slice())
+        + size_of_val(encoding.styles.as_slice())) as u64
+}
+
+// Command encoder shared by custom passes, work recorded in `PreRender` is submitted
+// before vello draws the frame and work recorded after that is submitted before present
+#[derive(Component, Default)]
+pub struct FrameEncoder {
+    encoder: Option<wgpu::CommandEncoder>,
+}
+
+impl FrameEncoder {
+    pub fn encoder(&mut self, device: &Device) -> &mut wgpu::CommandEncoder {
+        self.encoder.get_or_insert_with(|| {
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("frame"),
+            })
+        })
+    }
+
+    fn submit(&mut self, queue: &Queue) {
+        if let Some(encoder) = self.encoder.take() {
+            queue.submit(Some(encoder.finish()));
+        }
+    }
+}
+
+// The renderer and the resources it builds on the device
+struct Renderer {
+    vello: Vello,
+    blitter: Blitter,
+    post_processor: PostProcessor,
+    thread: Option<RenderThread>,
+}
+
+impl Renderer {
+    fn new(world: &World) -> Self {
+        let mode = world
+            .try_get::<&RenderMode>(|mode| *mode)
+            .unwrap_or_default();
+        world.get::<&mut WGPU>(|wgpu| {
+            let mut vello = Vello::new(wgpu);
+            let thread = (mode == RenderMode::Pipelined).then(|| {
+                let rasterizer = vello.rasterizer.take().unwrap().into_inner().unwrap();
+                RenderThread::spawn(wgpu, rasterizer)
+            });
+            Self {
+                vello,
+                blitter: Blitter::new(&wgpu.device),
+                post_processor: PostProcessor::new(&wgpu.device),
+                thread,
+            }
+        })
+    }
+
+    fn set(self, world: &World) {
+        if let Some(thread) = self.thread {
+            world.set(thread);
+        }
+        world.set(self.vello);
+        world.set(self.blitter);
+        world.set(self.post_processor);
+    }
+
+    // Swaps the singletons in place, a `set` from a system would only land once the
+    // frame's other render systems already ran with the lost device's resources
+    fn replace(self, world: &World) {
+        if let Some(thread) = self.thread {
+            world.get::<&mut RenderThread>(|current| *current = thread);
+        }
+        world.get::<&mut Vello>(|current| *current = self.vello);
+        world.get::<&mut Blitter>(|current| *current = self.blitter);
+        world.get::<&mut PostProcessor>(|current| *current = self.post_processor);
+    }
+}
+
 #[derive(Component)]
 pub struct RenderModule;
 
@@ -69,26 +638,138 @@ impl Module for RenderModule {
     fn module(world: &World) {
         world.module::<Self>("module");
 
-        world.get::<&mut WGPU>(|wgpu| {
-            world.set(Vello::new(wgpu));
-        });
+        Renderer::new(world).set(world);
+        world.set(Readbacks::default());
+        world.set(FrameEncoder::default());
+        world.set(FrameCounter::default());
+        world.component::<RenderStats>().meta();
+        world.component::<SceneStats>().meta();
+        world.set(RenderStats::default());
+        world.set(FrameStats::default());
+
+        render_phases(world);
 
         // Respond to window events
-        observer!("resize_window", world, Resize, &WGPU($), &mut Window).each_iter(
-            |it, _, (wgpu, window)| {
+        observer!("resize_window", world, Resize, &WGPU($), &mut Window, &mut RenderTarget).each_iter(
+            |it, _, (wgpu, window, target)| {
                 let data = it.param();
                 // Reconfigure the surface with the new size
                 window.config.width = data.width.max(1);
                 window.config.height = data.height.max(1);
-                window.surface.configure(&wgpu.device, &window.config);
+                window.configure_surface(&wgpu.device);
+                *target = RenderTarget::new(&wgpu.device, window.config.width, window.config.height);
+            },
+        );
+
+        // Rebuilds everything created from the device once it is lost
+        world
+            .system_named::<()>("recover_device")
+            .kind::<flecs::pipeline::OnLoad>()
+            .run(|it| {
+                let world = it.world();
+                if !world.get::<&WGPU>(|wgpu| wgpu.is_lost()) {
+                    return;
+                }
+                let backend = world
+                    .try_get::<&RenderBackend>(|backend| *backend)
+                    .unwrap_or_default();
+
+                let windows = world.new_query::<(&mut Window, &SurfaceSettings)>();
+                let mut surface = None;
+                windows.each(|(window, _)| {
+                    surface.get_or_insert_with(|| window.surface.clone());
+                });
+                world.get::<&mut WGPU>(|wgpu| {
+                    pollster::block_on(wgpu.recreate(surface.as_deref(), backend));
+                    windows.each(|(window, settings)| window.configure(wgpu, settings));
+                    world.new_query::<&mut RenderTarget>().each(|target| {
+                        *target = RenderTarget::new(&wgpu.device, target.width(), target.height());
+                    });
+                });
+                Renderer::new(&world).replace(&world);
+                world.get::<&mut Readbacks>(|readbacks| readbacks.clear());
+                world.get::<&mut FrameEncoder>(|encoder| *encoder = FrameEncoder::default());
+
+                world
+                    .event()
+                    .add::<WGPU>()
+                    .entity(world.entity_from::<WGPU>())
+                    .emit(&DeviceRecreated);
+            });
+
+        observer!("configure_surface", world, flecs::OnSet, &SurfaceSettings, &WGPU($), &mut Window)
+            .each(|(settings, wgpu, window)| window.configure(wgpu, settings));
+
+        // Moving a viewport only changes where the scene is composited
+        observer!("move_viewport", world, flecs::OnSet, &Viewport, &mut VelloScene)
+            .each(|(_, scene)| scene.mark_dirty());
+
+        observer!("remove_viewport", world, flecs::OnRemove, &Viewport, &mut VelloScene)
+            .each(|(_, scene)| scene.mark_dirty());
+
+        // Effects are applied as the target renders, so changing them renders it again
+        observer!("set_post_process", world, flecs::OnSet, &PostProcess, &mut RenderTarget)
+            .each(|(_, target)| target.stale = true);
+
+        observer!("remove_post_process", world, flecs::OnRemove, &PostProcess, &mut RenderTarget)
+            .each(|(_, target)| target.stale = true);
+
+        observer!("set_antialiasing", world, flecs::OnSet, &Antialiasing, &mut RenderTarget)
+            .each(|(_, target)| target.stale = true);
+
+        observer!("remove_antialiasing", world, flecs::OnRemove, &Antialiasing, &mut RenderTarget)
+            .each(|(_, target)| target.stale = true);
+
+        observer!("request_screenshot", world, Screenshot, &mut Window).each_iter(
+            |it, _, window| {
+                window.screenshots.push(it.param().path.clone());
+                window.request_redraw();
             },
         );
 
+        // Shapes are encoded with the camera applied, so moving it re-encodes the scene
         world
-            .system_named::<&mut Window>("create_texture")
+            .system_named::<&mut VelloScene>("track_camera")
+            .kind::<flecs::pipeline::PreStore>()
+            .each(|scene| {
+                if scene.camera != scene.encoded_camera {
+                    scene.encoded_camera = scene.camera;
+                    scene.invalidate();
+                }
+            });
+
+        // Shapes outside the visible rect are culled, so the scene is encoded again when
+        // more of it comes into view
+        system!("update_visible_rect", world, &mut VelloScene, ?&Viewport, ?&RenderTarget(up), ?&Window(up))
+            .kind::<flecs::pipeline::PreStore>()
+            .each(|(scene, viewport, target, window)| {
+                let scale_factor = window.map_or(1.0, |window| window.scale_factor());
+                let visible = match (viewport, target) {
+                    (Some(viewport), _) => {
+                        let size = viewport.physical_rect(scale_factor).size();
+                        Some(vello::kurbo::Rect::from_origin_size(vello::kurbo::Point::ZERO, size))
+                    }
+                    (None, Some(target)) => Some(vello::kurbo::Rect::new(
+                        0.0,
+                        0.0,
+                        target.width() as f64,
+                        target.height() as f64,
+                    )),
+                    (None, None) => None,
+                };
+                if visible != scene.visible {
+                    scene.visible = visible;
+                    scene.invalidate();
+                }
+            });
+
+        // Frames are cleared as they are acquired, before anything in `PreRender` draws
+        // into them
+        system!("create_texture", world, &WGPU($), &mut FrameEncoder($), &mut FrameStats($), &mut Window)
             .kind::<flecs::pipeline::OnStore>()
-            .each(|window| {
-                if !window.redraw {
+            .each(|(wgpu, encoder, stats, window)| {
+                let _timer = Timer::new(&mut stats.create_texture_ms);
+                if !window.redraw || window.pipelined {
                     return;
                 }
                 let Ok(frame) = window.surface.get_current_texture() else {
@@ -98,17 +779,100 @@ impl Module for RenderModule {
                 let view = frame
                     .texture
                     .create_view(&wgpu::TextureViewDescriptor::default());
+                encoder
+                    .encoder(&wgpu.device)
+                    .begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("clear_frame"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                                store: wgpu::StoreOp::Store,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
 
                 window.texture = Some(frame);
                 window.view = Some(view);
             });
 
-        system!("render_vello_scene", world, &mut WGPU($), &mut Vello($), &mut Window(up), &mut VelloScene)
-            .kind::<flecs::pipeline::OnStore>()
-            .each(|(wgpu, vello, window, scene)| {
-                if scene.encoding().is_empty() {
+        system!("submit_pre_render", world, &WGPU($), &mut FrameEncoder($))
+            .kind::<Render>()
+            .each(|(wgpu, encoder)| encoder.submit(&wgpu.queue));
+
+        // Composites the window's scenes bottom to top and renders them in one pass,
+        // skipped when every scene is clean and the target already holds them
+        system!("render_vello_scene", world, &mut WGPU($), &mut Vello($), &mut PostProcessor($), &mut FrameCounter($), &mut FrameStats($), ?&mut RenderThread($), &mut RenderTarget, ?&Window, ?&PostProcess, ?&Antialiasing)
+            .kind::<Render>()
+            .each_entity(|e, (wgpu, vello, post_processor, counter, stats, thread, target, window, post, antialiasing)| {
+                let _timer = Timer::new(&mut stats.render_ms);
+                let mut layers = Vec::new();
+                let mut dirty = target.stale;
+                e.each_child(|child| {
+                    if let Some((layer, scene_dirty)) =
+                        child.try_get::<&VelloScene>(|scene| (scene.layer, scene.dirty))
+                    {
+                        layers.push((layer, child.id()));
+                        dirty |= scene_dirty;
+                    }
+                });
+                // Stable, so scenes on the same layer keep their creation order
+                layers.sort_by_key(|(layer, _)| *layer);
+                let scenes: Vec<Entity> = layers.into_iter().map(|(_, scene)| scene).collect();
+
+                let Some(&bottom) = scenes.first() else {
+                    return;
+                };
+                // Scenes were added, removed or reordered
+                dirty |= scenes != target.scenes;
+                if !dirty {
+                    counter.skipped += 1;
+                    return;
+                }
+                let world = e.world();
+                let base_color = bottom
+                    .entity_view(world)
+                    .get::<&VelloScene>(|scene| scene.base_color);
+
+                // Viewports on headless targets are always in physical pixels
+                let scale_factor = window.map_or(1.0, |window| window.scale_factor());
+                vello.composite.reset();
+                for &scene in &scenes {
+                    scene.entity_view(world).get::<(&mut VelloScene, Option<&Viewport>)>(
+                        |(scene, viewport)| {
+                            let composite = &mut vello.composite;
+                            match viewport {
+                                Some(viewport) => {
+                                    let rect = viewport.physical_rect(scale_factor);
+                                    let identity = vello::kurbo::Affine::IDENTITY;
+                                    let offset = vello::kurbo::Affine::translate(rect.origin().to_vec2());
+                                    composite.push_layer(vello::peniko::Mix::Clip, 1.0, identity, &rect);
+                                    composite.fill(
+                                        vello::peniko::Fill::NonZero,
+                                        identity,
+                                        scene.base_color,
+                                        None,
+                                        &rect,
+                                    );
+                                    composite.append(&scene.scene, Some(offset));
+                                    composite.pop_layer();
+                                }
+                                None => composite.append(&scene.scene, None),
+                            }
+                            scene.dirty = false;
+                            scene.retained = true;
+                        },
+                    );
+                }
+                target.scenes = scenes;
+
+                if vello.composite.encoding().is_empty() {
                     // Add no-op shape to avoid debug assert
-                    scene.fill(
+                    vello.composite.fill(
                         vello::peniko::Fill::EvenOdd,
                         vello::kurbo::Affine::default(),
                         vello::peniko::Color::BLACK,
@@ -116,35 +880,179 @@ impl Module for RenderModule {
                         &vello::kurbo::Rect::new(0.0, 0.0, 0.0, 0.0),
                     );
                 }
-                if let Some(surface) = &window.texture {
-                    vello
-                        .renderer.lock().unwrap()
-                        .render_to_surface(
-                            &wgpu.device,
-                            &wgpu.queue,
-                            scene,
-                            surface,
-                            &vello::RenderParams {
-                                base_color: scene.base_color,
-                                width: window.config.width,
-                                height: window.config.height,
-                                antialiasing_method: vello::AaConfig::Area,
-                            },
-                        )
-                        .expect("Failed to render scene.");
+                let effects = post.map(|post| post.effects.as_slice()).unwrap_or_default();
+                let params = vello::RenderParams {
+                    base_color,
+                    width: target.width(),
+                    height: target.height(),
+                    antialiasing_method: antialiasing.map_or(vello::AaConfig::Area, |aa| aa.0),
                 };
-                scene.reset()
+                if let Some(thread) = thread {
+                    thread.push(RenderCommand::Render {
+                        window: e.id(),
+                        surface: window.map(|window| (window.surface.clone(), window.config.clone())),
+                        scene: Box::new(std::mem::take(&mut vello.composite)),
+                        texture: target.texture.clone(),
+                        view: target.view.clone(),
+                        params,
+                        effects: effects.to_vec(),
+                    });
+                    target.stale = false;
+                    counter.rendered += 1;
+                    return;
+                }
+
+                // The scenes are retained, so the composite is rendered once a frame is available
+                target.stale = window.is_some_and(|window| window.texture.is_none());
+                if target.stale {
+                    return;
+                }
+                if let Some(rasterizer) = &mut vello.rasterizer {
+                    rasterizer.get_mut().unwrap().render(
+                        &wgpu.device,
+                        &wgpu.queue,
+                        &vello.composite,
+                        &target.view,
+                        &params,
+                    );
+                }
+                post_processor.apply(&wgpu.device, &wgpu.queue, &target.texture, &target.view, effects);
+                counter.rendered += 1;
             });
 
-        world
-            .system_named::<&mut Window>("present_texture")
-            .kind::<flecs::pipeline::OnStore>()
-            .each(|window| {
+        system!("blit_to_surface", world, &WGPU($), &mut Blitter($), &RenderTarget, &Window)
+            .kind::<Render>()
+            .each(|(wgpu, blitter, target, window)| {
+                if let Some(view) = &window.view {
+                    let load = wgpu::LoadOp::Load;
+                    blitter.blit(&wgpu.device, &wgpu.queue, &target.view, view, window.config.format, load);
+                }
+            });
+
+        system!("submit_post_render", world, &WGPU($), &mut FrameEncoder($))
+            .kind::<Present>()
+            .each(|(wgpu, encoder)| encoder.submit(&wgpu.queue));
+
+        // Captures what vello rendered, custom passes drawn to the surface are not included
+        system!("capture_screenshots", world, &WGPU($), &mut Readbacks($), ?&mut RenderThread($), &RenderTarget, &mut Window)
+            .kind::<Present>()
+            .each(|(wgpu, readbacks, thread, target, window)| {
+                if let Some(thread) = thread {
+                    // Queued after the frame's render so they capture it
+                    for path in window.screenshots.drain(..) {
+                        thread.push(RenderCommand::Capture {
+                            texture: target.texture.clone(),
+                            target: ReadbackTarget::Png(path),
+                        });
+                    }
+                    return;
+                }
+                if window.texture.is_none() {
+                    return;
+                }
+                for path in window.screenshots.drain(..) {
+                    let png = ReadbackTarget::Png(path);
+                    readbacks.push(Readback::new(&wgpu.device, &wgpu.queue, &target.texture, png));
+                }
+            });
+
+        // Records every frame a target renders, headless targets render every frame
+        system!("capture_recordings", world, &WGPU($), &mut Readbacks($), ?&mut RenderThread($), &RenderTarget, &mut Recording, ?&Window)
+            .kind::<Present>()
+            .each(|(wgpu, readbacks, thread, target, recording, window)| {
+                // Windows only render once their frame has been acquired
+                if window.is_some_and(|window| window.texture.is_none() && !window.pipelined) {
+                    return;
+                }
+                let readback = match recording.next_frame(target.width(), target.height()) {
+                    Ok(readback) => readback,
+                    Err(err) => {
+                        eprintln!("Failed to record to {}: {err}", recording.dir.display());
+                        return;
+                    }
+                };
+                match thread {
+                    Some(thread) => thread.push(RenderCommand::Capture {
+                        texture: target.texture.clone(),
+                        target: readback,
+                    }),
+                    None => readbacks.push(Readback::new(
+                        &wgpu.device,
+                        &wgpu.queue,
+                        &target.texture,
+                        readback,
+                    )),
+                }
+            });
+
+        // Recording runs the simulation on a fixed step
+        observer!("start_recording", world, flecs::OnSet, &Recording).each_iter(|it, _, recording| {
+            it.world().set(FixedStep(recording.step()));
+        });
+
+        // Other recordings keep the fixed step, the removed one still matches during OnRemove
+        observer!("stop_recording", world, flecs::OnRemove, &Recording).each_iter(|it, index, _| {
+            let world = it.world();
+            let removed = it.entity(index).id();
+            let mut step = None;
+            world.new_query::<&Recording>().each_entity(|e, recording| {
+                if e.id() != removed {
+                    step.get_or_insert(recording.step());
+                }
+            });
+            match step {
+                Some(step) => {
+                    world.set(FixedStep(step));
+                }
+                None => {
+                    world.remove::<FixedStep>();
+                }
+            }
+        });
+
+        system!("send_frame", world, &mut RenderThread($))
+            .kind::<Present>()
+            .each(|thread| thread.send());
+
+        system!("present_texture", world, &mut FrameStats($), &mut Window)
+            .kind::<Present>()
+            .each(|(stats, window)| {
+                let _timer = Timer::new(&mut stats.present_ms);
                 if let Some(texture) = window.texture.take() {
                     texture.present();
                     window.redraw = false;
                     window.view = None;
                 }
             });
+
+        system!("update_scene_stats", world, &mut FrameStats($), &VelloScene)
+            .kind::<Present>()
+            .each_entity(|e, (stats, scene)| {
+                let scene_stats = SceneStats {
+                    shapes: scene.encoded as u32,
+                    culled: scene.culled as u32,
+                    encoding_bytes: encoding_bytes(scene),
+                };
+                stats.encoding_bytes += scene_stats.encoding_bytes;
+                e.set(scene_stats);
+            });
+
+        // Published at the end of the frame so that the explorer never sees a partial one
+        system!("publish_render_stats", world, &mut RenderStats($), &mut FrameStats($), ?&mut TextWriter($))
+            .kind::<Present>()
+            .each(|(stats, frame, text)| {
+                frame.glyph_runs = text.map_or(0, |text| text.take_glyph_runs());
+                *stats = std::mem::take(&mut frame.0);
+            });
+
+        system!("poll_readbacks", world, &WGPU($), &mut Readbacks($))
+            .kind::<Present>()
+            .each(|(wgpu, readbacks)| {
+                if readbacks.is_empty() {
+                    return;
+                }
+                wgpu.device.poll(wgpu::Maintain::Poll);
+                readbacks.retain(|readback| !readback.poll());
+            });
     }
 }
@@ -0,0 +1,153 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+use flecs_ecs::prelude::*;
+use wgpu::{Device, Queue, Surface, SurfaceConfiguration, Texture, TextureView};
+
+use crate::{
+    blit::Blitter,
+    post::{Effect, PostProcessor},
+    render::Rasterizer,
+    screenshot::{Readback, ReadbackTarget},
+    WGPU,
+};
+
+// Work extracted from a frame for the render thread, run in order
+pub(crate) enum RenderCommand {
+    // Renders a target's composited scenes and presents them if it is a window's
+    Render {
+        // The window or headless target
+        window: Entity,
+        surface: Option<(Arc<Surface<'static>>, SurfaceConfiguration)>,
+        // Boxed, the encoding is much larger than a capture
+        scene: Box<vello::Scene>,
+        texture: Arc<Texture>,
+        view: Arc<TextureView>,
+        params: vello::RenderParams,
+        effects: Vec<Effect>,
+    },
+    Capture {
+        texture: Arc<Texture>,
+        target: ReadbackTarget,
+    },
+}
+
+// Singleton present in pipelined mode, collects the frame's commands and hands them
+// to the render thread at the end of the frame. The device and queue, window surfaces
+// and target textures are behind `Arc`s so that the thread can share them.
+#[derive(Component)]
+pub(crate) struct RenderThread {
+    commands: Vec<RenderCommand>,
+    sender: Option<SyncSender<Vec<RenderCommand>>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl RenderThread {
+    pub(crate) fn spawn(wgpu: &WGPU, rasterizer: Rasterizer) -> Self {
+        // A rendezvous channel, so the frame after next waits for the render thread
+        // rather than queueing up behind it
+        let (sender, receiver) = mpsc::sync_channel(0);
+        let device = wgpu.device.clone();
+        let queue = wgpu.queue.clone();
+        let handle = std::thread::Builder::new()
+            .name("render".to_string())
+            .spawn(move || run(device, queue, rasterizer, receiver))
+            .expect("Failed to spawn render thread.");
+        Self {
+            commands: Vec::new(),
+            sender: Some(sender),
+            handle: Some(handle),
+        }
+    }
+
+    pub(crate) fn push(&mut self, command: RenderCommand) {
+        self.commands.push(command);
+    }
+
+    // Blocks until the render thread has finished the previous frame
+    pub(crate) fn send(&mut self) {
+        if self.commands.is_empty() {
+            return;
+        }
+        let commands = std::mem::take(&mut self.commands);
+        if let Some(sender) = &self.sender {
+            sender.send(commands).expect("Render thread exited.");
+        }
+    }
+}
+
+impl Drop for RenderThread {
+    fn drop(&mut self) {
+        // Closing the channel ends the render loop
+        self.sender.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn run(
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    mut rasterizer: Rasterizer,
+    receiver: Receiver<Vec<RenderCommand>>,
+) {
+    let mut blitter = Blitter::new(&device);
+    let mut post_processor = PostProcessor::new(&device);
+    // Configs last applied to each window's surface
+    let mut configured: HashMap<Entity, SurfaceConfiguration> = HashMap::new();
+
+    for commands in receiver {
+        for command in commands {
+            match command {
+                RenderCommand::Render {
+                    window,
+                    surface,
+                    scene,
+                    texture,
+                    view,
+                    params,
+                    effects,
+                } => {
+                    rasterizer.render(&device, &queue, &scene, &view, &params);
+                    post_processor.apply(&device, &queue, &texture, &view, &effects);
+
+                    let Some((surface, config)) = surface else {
+                        continue;
+                    };
+                    if configured.get(&window) != Some(&config) {
+                        surface.configure(&device, &config);
+                        configured.insert(window, config.clone());
+                    }
+
+                    let Ok(frame) = surface.get_current_texture() else {
+                        continue;
+                    };
+                    let frame_view = frame
+                        .texture
+                        .create_view(&wgpu::TextureViewDescriptor::default());
+                    // Nothing else draws into frames acquired on the render thread
+                    let clear = wgpu::LoadOp::Clear(wgpu::Color::BLACK);
+                    blitter.blit(&device, &queue, &view, &frame_view, config.format, clear);
+                    frame.present();
+                }
+                RenderCommand::Capture { texture, target } => {
+                    let readback = Readback::new(&device, &queue, &texture, target);
+                    // Blocking is fine here, the main thread is not waiting on it
+                    loop {
+                        device.poll(wgpu::Maintain::Wait);
+                        if readback.poll() {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use std::{
+    error::Error,
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU8, Ordering},
+        mpsc::{self, Sender},
+        Arc, OnceLock,
+    },
+};
+
+use deref_derive::{Deref, DerefMut};
+use flecs_ecs::prelude::*;
+use wgpu::{Device, Queue, Texture};
+
+use crate::recording::FrameSender;
+
+// Emit on a window entity to save its next frame as a PNG, e.g.
+// `world.event().add::<Window>().entity(window).emit(&Screenshot { path })`
+#[derive(Component)]
+pub struct Screenshot {
+    pub path: PathBuf,
+}
+
+const PENDING: u8 = 0;
+const MAPPED: u8 = 1;
+const FAILED: u8 = 2;
+
+// Where a frame goes once it has been read back
+pub(crate) enum ReadbackTarget {
+    // One-off screenshots
+    Png(PathBuf),
+    // Sent to a recording's writer thread, which keeps frames in order
+    Stream(FrameSender),
+}
+
+// A frame copied into a buffer that is waiting to be mapped for reading, always from a
+// `RenderTarget` so the data is already RGBA
+pub(crate) struct Readback {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    state: Arc<AtomicU8>,
+    target: ReadbackTarget,
+}
+
+impl Readback {
+    pub(crate) fn new(
+        device: &Device,
+        queue: &Queue,
+        texture: &Texture,
+        target: ReadbackTarget,
+    ) -> Self {
+        let width = texture.width();
+        let height = texture.height();
+        let padded_bytes_per_row = (width * 4).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: (padded_bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("readback"),
+        });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        queue.submit(Some(encoder.finish()));
+
+        let state = Arc::new(AtomicU8::new(PENDING));
+        let mapped = state.clone();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let state = if result.is_ok() { MAPPED } else { FAILED };
+                mapped.store(state, Ordering::Release);
+            });
+
+        Self {
+            buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+            state,
+            target,
+        }
+    }
+
+    // Returns true once the readback is finished with, encoding happens off-thread
+    pub(crate) fn poll(&self) -> bool {
+        match self.state.load(Ordering::Acquire) {
+            PENDING => false,
+            MAPPED => {
+                let data = self.read_rgba();
+                let (width, height) = (self.width, self.height);
+                match &self.target {
+                    ReadbackTarget::Png(path) => {
+                        let _ = screenshot_writer().send((path.clone(), width, height, data));
+                    }
+                    ReadbackTarget::Stream(sender) => {
+                        // The stream was closed if the recording was removed
+                        let _ = sender.send((width, height, data));
+                    }
+                }
+                true
+            }
+            _ => {
+                eprintln!("Failed to read back frame.");
+                true
+            }
+        }
+    }
+
+    fn read_rgba(&self) -> Vec<u8> {
+        let row_bytes = (self.width * 4) as usize;
+        let mut data = Vec::with_capacity(row_bytes * self.height as usize);
+        {
+            let mapped = self.buffer.slice(..).get_mapped_range();
+            for row in mapped.chunks(self.padded_bytes_per_row as usize) {
+                data.extend_from_slice(&row[..row_bytes]);
+            }
+        }
+        self.buffer.unmap();
+        data
+    }
+}
+
+// Readbacks in flight, polled every frame
+#[derive(Component, Default, Deref, DerefMut)]
+pub(crate) struct Readbacks(Vec<Readback>);
+
+// Screenshots from every window and the render thread share one writer thread
+fn screenshot_writer() -> &'static Sender<(PathBuf, u32, u32, Vec<u8>)> {
+    static WRITER: OnceLock<Sender<(PathBuf, u32, u32, Vec<u8>)>> = OnceLock::new();
+    WRITER.get_or_init(|| {
+        let (sender, receiver) = mpsc::channel::<(PathBuf, u32, u32, Vec<u8>)>();
+        std::thread::spawn(move || {
+            for (path, width, height, data) in receiver {
+                if let Err(err) = write_png(&path, width, height, &data) {
+                    eprintln!("Failed to write {}: {err}", path.display());
+                }
+            }
+        });
+        sender
+    })
+}
+
+pub(crate) fn write_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    data: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(data)?;
+    Ok(())
+}
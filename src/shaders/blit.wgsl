@@ -0,0 +1,32 @@
+// Copies vello's render target to a window surface
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+// Fullscreen triangle
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Vello writes sRGB encoded values, which unorm surfaces take as is. Colors are
+// premultiplied for blending over what was drawn into the frame before.
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = textureLoad(source, vec2<i32>(position.xy), 0);
+    return vec4<f32>(color.rgb * color.a, color.a);
+}
+
+// sRGB and float surfaces expect linear values
+@fragment
+fn fs_linear(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = textureLoad(source, vec2<i32>(position.xy), 0);
+    return vec4<f32>(srgb_to_linear(color.rgb) * color.a, color.a);
+}
+
+fn srgb_to_linear(color: vec3<f32>) -> vec3<f32> {
+    let lower = color / 12.92;
+    let higher = pow((color + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(higher, lower, color <= vec3<f32>(0.04045));
+}
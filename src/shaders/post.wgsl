@@ -0,0 +1,85 @@
+// Post-processing effects, each pass reads the render target and writes a copy of it
+
+struct Params {
+    values: vec4<f32>,
+    extra: vec4<f32>,
+}
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<uniform> params: Params;
+
+// Fullscreen triangle
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// Clamps to the edge of the texture
+fn load(position: vec2<i32>) -> vec4<f32> {
+    let size = vec2<i32>(textureDimensions(source));
+    return textureLoad(source, clamp(position, vec2<i32>(0), size - 1), 0);
+}
+
+// values: threshold, intensity, radius
+@fragment
+fn fs_bloom(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let center = vec2<i32>(position.xy);
+    let color = load(center);
+    var glow = vec3<f32>(0.0);
+    var total = 0.0;
+    // Rings of taps, nearer rings weigh more
+    for (var ring = 1; ring <= 4; ring++) {
+        let distance = params.values.z * f32(ring) / 4.0;
+        let weight = 1.0 - f32(ring - 1) / 4.0;
+        for (var i = 0; i < 8; i++) {
+            let angle = f32(i) * 0.785398 + f32(ring) * 0.392699;
+            let offset = vec2<i32>(round(vec2<f32>(cos(angle), sin(angle)) * distance));
+            let bright = max(load(center + offset).rgb - vec3<f32>(params.values.x), vec3<f32>(0.0));
+            glow += bright * weight;
+            total += weight;
+        }
+    }
+    return vec4<f32>(color.rgb + glow / total * params.values.y, color.a);
+}
+
+// values: strength, radius where darkening starts as a fraction of the half diagonal
+@fragment
+fn fs_vignette(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(source));
+    let color = load(vec2<i32>(position.xy));
+    let distance = length(position.xy / size * 2.0 - 1.0) / sqrt(2.0);
+    let shade = 1.0 - params.values.x * smoothstep(params.values.y, 1.0, distance);
+    return vec4<f32>(color.rgb * shade, color.a);
+}
+
+// values: radius in pixels, unused, direction
+@fragment
+fn fs_blur(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let center = vec2<i32>(position.xy);
+    let radius = i32(params.values.x);
+    let direction = vec2<i32>(params.values.zw);
+    let sigma = max(params.values.x / 2.0, 0.5);
+    var color = vec4<f32>(0.0);
+    var total = 0.0;
+    for (var i = -radius; i <= radius; i++) {
+        let weight = exp(-f32(i * i) / (2.0 * sigma * sigma));
+        color += load(center + direction * i) * weight;
+        total += weight;
+    }
+    return color / total;
+}
+
+// values: exposure in stops, contrast, saturation; extra: tint
+@fragment
+fn fs_color_grade(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let color = load(vec2<i32>(position.xy));
+    var rgb = color.rgb * exp2(params.values.x);
+    rgb = (rgb - 0.5) * params.values.y + 0.5;
+    let luma = dot(rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    rgb = mix(vec3<f32>(luma), rgb, params.values.z) * params.extra.rgb;
+    return vec4<f32>(clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0)), color.a);
+}
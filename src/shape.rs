@@ -1,37 +1,59 @@
 use deref_derive::{Deref, DerefMut};
 use flecs_ecs::prelude::*;
-use vello::kurbo::{Affine, Vec2};
+use vello::kurbo::{Affine, Point, Shape, Vec2};
 
-use crate::VelloScene;
+use crate::{
+    render::FrameStats,
+    svg::{export_shape, Exporter},
+    PreRender, VelloScene,
+};
 
+// Paints the inside of a shape with `brush`, a color, gradient or image. The brush is in
+// the shape's local space, so it moves with the shape, and `brush_transform` is applied
+// to it on top of that.
 #[derive(Component)]
 pub struct Fill {
     pub style: vello::peniko::Fill,
-    pub color: vello::peniko::Color,
+    pub brush: vello::peniko::Brush,
+    pub brush_transform: Option<Affine>,
 }
 
 impl Fill {
-    pub fn new(color: vello::peniko::Color) -> Self {
+    pub fn new(brush: impl Into<vello::peniko::Brush>) -> Self {
         Self {
             style: vello::peniko::Fill::NonZero,
-            color,
+            brush: brush.into(),
+            brush_transform: None,
         }
     }
+
+    pub fn with_brush_transform(mut self, transform: Affine) -> Self {
+        self.brush_transform = Some(transform);
+        self
+    }
 }
 
+// Paints the outline of a shape, the brush is placed as for `Fill`
 #[derive(Component)]
 pub struct Stroke {
     pub style: vello::kurbo::Stroke,
-    pub color: vello::peniko::Color,
+    pub brush: vello::peniko::Brush,
+    pub brush_transform: Option<Affine>,
 }
 
 impl Stroke {
-    pub fn new(width: f64, color: vello::peniko::Color) -> Self {
+    pub fn new(width: f64, brush: impl Into<vello::peniko::Brush>) -> Self {
         Self {
             style: vello::kurbo::Stroke::new(width),
-            color,
+            brush: brush.into(),
+            brush_transform: None,
         }
     }
+
+    pub fn with_brush_transform(mut self, transform: Affine) -> Self {
+        self.brush_transform = Some(transform);
+        self
+    }
 }
 
 #[derive(Component, Deref, DerefMut, Default)]
@@ -43,42 +65,284 @@ impl Transform {
     }
 }
 
+// A shape's transform relative to its scene, the `Transform`s of its parents applied to
+// its own. Set by `propagate_transforms` for every entity with a `Transform` under a
+// scene, entities without one group their children without moving them.
+#[derive(Component, Deref, DerefMut, Default, Clone, Copy, PartialEq, Debug)]
+pub struct GlobalTransform(pub Affine);
+
+// Paint order of shapes across every shape type within a scene, higher is painted on
+// top. Shapes without one take their closest parent's, or 0. Shapes on the same z are
+// painted in order of their entity ids.
+#[derive(Component, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct ZIndex(pub i32);
+
+// Encodes one entity's shape if it has shape type `T`
+type Draw = fn(EntityView, &mut VelloScene);
+
+// Tests a point in the entity's local space against its shape if it has shape type `T`
+type HitTest = fn(EntityView, Point) -> bool;
+
+// Every registered shape type, for picking and export which handle all of them at once.
+// Filled in by `VelloShape::systems`, so shape types defined outside this crate are
+// picked and exported as well.
+#[derive(Component, Default)]
+pub(crate) struct ShapeTypes {
+    pub(crate) hit_tests: Vec<HitTest>,
+    pub(crate) exporters: Vec<Exporter>,
+}
+
+// A shape in view waiting to be encoded, scenes sort these into paint order first
+pub(crate) struct DrawItem {
+    z: i32,
+    entity: Entity,
+    draw: Draw,
+}
+
+// Call `systems` for each shape type once `VelloShapeModule` is imported
 pub trait VelloShape: ComponentId {
     fn systems(world: &World) {
-        system!(world, &mut VelloScene(up), &Stroke, &Transform, &Self).each(
-            |(scene, stroke, transform, shape)| {
-                shape.stroke(scene, stroke, scene.camera * **transform);
-            },
-        );
+        world.get::<&mut ShapeTypes>(|types| {
+            types.hit_tests.push(hit_shape::<Self>);
+            types.exporters.push(export_shape::<Self>);
+        });
 
-        system!(world, &mut VelloScene(up), &Fill, &Transform, &Self).each(
-            |(scene, fill, transform, shape)| {
-                shape.fill(scene, fill, scene.camera * **transform);
-            },
-        );
+        // Scenes keep their encoding between frames, so a change to any of a shape's
+        // components marks its scene for re-encoding
+        system!(world, &GlobalTransform, &Self, ?&Fill, ?&Stroke)
+            .kind::<flecs::pipeline::PreStore>()
+            .detect_changes()
+            .run(|mut it| {
+                while it.next() {
+                    if !it.is_changed() {
+                        continue;
+                    }
+                    for i in it.iter() {
+                        invalidate_scene(it.entity(i));
+                    }
+                }
+            });
+
+        observer!(world, flecs::OnRemove, &Self).each_entity(|e, _| invalidate_scene(e));
+
+        // Shapes in view are collected into their scene's draw list, `encode_shapes`
+        // encodes them once every shape type has been collected
+        system!(world, &mut FrameStats($), &mut VelloScene(up), ?&Stroke, ?&Fill, &GlobalTransform, &Self)
+            .kind::<flecs::pipeline::OnStore>()
+            .each_entity(|e, (stats, scene, stroke, fill, transform, shape)| {
+                if scene.retained || (stroke.is_none() && fill.is_none()) {
+                    return;
+                }
+                let transform = scene.camera * **transform;
+                if !in_view(scene, shape, transform, fill, stroke) {
+                    scene.culled += 1;
+                    stats.shapes_culled += 1;
+                    return;
+                }
+                scene.encoded += 1;
+                stats.shapes_encoded += 1;
+                scene.draw_list.push(DrawItem {
+                    z: z_index(e),
+                    entity: e.id(),
+                    draw: draw_shape::<Self>,
+                });
+            });
     }
 
     fn shape(&self) -> impl vello::kurbo::Shape;
 
+    // Open shapes have no interior, so their fill is painted as a stroke of this style
+    fn fill_stroke(&self) -> Option<vello::kurbo::Stroke> {
+        None
+    }
+
     fn fill(&self, scene: &mut VelloScene, fill: &Fill, transform: impl Into<Affine>) {
-        scene.fill(
-            fill.style,
-            transform.into(),
-            fill.color,
-            None,
-            &self.shape(),
-        );
+        match self.fill_stroke() {
+            Some(style) => scene.stroke(
+                &style,
+                transform.into(),
+                &fill.brush,
+                fill.brush_transform,
+                &self.shape(),
+            ),
+            None => scene.fill(
+                fill.style,
+                transform.into(),
+                &fill.brush,
+                fill.brush_transform,
+                &self.shape(),
+            ),
+        }
     }
 
     fn stroke(&self, scene: &mut VelloScene, stroke: &Stroke, transform: impl Into<Affine>) {
         scene.stroke(
             &stroke.style,
             transform.into(),
-            stroke.color,
-            None,
+            &stroke.brush,
+            stroke.brush_transform,
             &self.shape(),
         );
     }
+
+    // Tests a point in the shape's local space against whatever is painted for it
+    fn hit_test(&self, point: Point, fill: Option<&Fill>, stroke: Option<&Stroke>) -> bool {
+        let shape = self.shape();
+        let filled = match (fill, self.fill_stroke()) {
+            (Some(_), Some(style)) => stroke_contains(&shape, &style, point),
+            (Some(_), None) => shape.contains(point),
+            (None, _) => false,
+        };
+        filled || stroke.is_some_and(|stroke| stroke_contains(&shape, &stroke.style, point))
+    }
+}
+
+fn draw_shape<T: VelloShape>(entity: EntityView, scene: &mut VelloScene) {
+    entity.try_get::<(&T, &GlobalTransform, Option<&Fill>, Option<&Stroke>)>(
+        |(shape, transform, fill, stroke)| {
+            let transform = scene.camera * **transform;
+            // Strokes paint over the fill of the same shape
+            if let Some(fill) = fill {
+                shape.fill(scene, fill, transform);
+            }
+            if let Some(stroke) = stroke {
+                shape.stroke(scene, stroke, transform);
+            }
+        },
+    );
+}
+
+fn hit_shape<T: VelloShape>(entity: EntityView, point: Point) -> bool {
+    entity
+        .try_get::<(&T, Option<&Fill>, Option<&Stroke>)>(|(shape, fill, stroke)| {
+            shape.hit_test(point, fill, stroke)
+        })
+        .unwrap_or(false)
+}
+
+// The shape's own `ZIndex` or the closest one among its parents within its scene
+pub(crate) fn z_index(shape: EntityView) -> i32 {
+    let mut current = Some(shape);
+    while let Some(entity) = current {
+        if entity.has::<VelloScene>() {
+            break;
+        }
+        if let Some(z) = entity.try_get::<&ZIndex>(|z| z.0) {
+            return z;
+        }
+        current = entity.parent();
+    }
+    0
+}
+
+// Marks the scene a shape is drawn into for re-encoding
+fn invalidate_scene(shape: EntityView) {
+    let mut parent = shape.parent();
+    while let Some(entity) = parent {
+        if entity
+            .try_get::<&mut VelloScene>(|scene| scene.invalidate())
+            .is_some()
+        {
+            return;
+        }
+        parent = entity.parent();
+    }
+}
+
+// The scene space transform the children of `entity` are placed in, `None` outside of a
+// scene. Built from local transforms since `GlobalTransform`s set this frame are deferred.
+fn parent_space(entity: EntityView) -> Option<Affine> {
+    let mut space = Affine::IDENTITY;
+    let mut current = entity.parent();
+    while let Some(parent) = current {
+        if parent.has::<VelloScene>() {
+            return Some(space);
+        }
+        if let Some(local) = parent.try_get::<&Transform>(|transform| **transform) {
+            space = local * space;
+        }
+        current = parent.parent();
+    }
+    None
+}
+
+// Sets the scene space transform of `entity` and everything under it
+fn refresh(entity: EntityView) {
+    // Nested scenes have their own space
+    if entity.has::<VelloScene>() {
+        return;
+    }
+    let Some(mut global) = parent_space(entity) else {
+        return;
+    };
+    if let Some(local) = entity.try_get::<&Transform>(|transform| **transform) {
+        global = global * local;
+        if entity.try_get::<&GlobalTransform>(|cached| **cached) != Some(global) {
+            entity.set(GlobalTransform(global));
+        }
+    }
+    propagate(entity, global);
+}
+
+// Sets the scene space transform of everything under `entity`. Only transforms that moved
+// are set, so that scenes with nothing moving in them are not encoded again.
+fn propagate(entity: EntityView, parent: Affine) {
+    entity.each_child(|child| {
+        if child.has::<VelloScene>() {
+            return;
+        }
+        let mut global = parent;
+        if let Some(local) = child.try_get::<&Transform>(|transform| **transform) {
+            global = parent * local;
+            if child.try_get::<&GlobalTransform>(|cached| **cached) != Some(global) {
+                child.set(GlobalTransform(global));
+            }
+        }
+        propagate(child, global);
+    });
+}
+
+// Whether anything painted for a shape lands in the scene's visible rect
+fn in_view<T: VelloShape>(
+    scene: &VelloScene,
+    shape: &T,
+    transform: Affine,
+    fill: Option<&Fill>,
+    stroke: Option<&Stroke>,
+) -> bool {
+    let Some(visible) = scene.visible else {
+        return true;
+    };
+    let mut reach = stroke.map_or(0.0, |stroke| stroke_reach(&stroke.style));
+    if let (Some(_), Some(style)) = (fill, shape.fill_stroke()) {
+        reach = reach.max(stroke_reach(&style));
+    }
+    // Strokes are scaled by the transform, so the bounds are inflated in local space
+    let bounds = transform.transform_rect_bbox(shape.shape().bounding_box().inflate(reach, reach));
+    bounds.x0 <= visible.x1
+        && visible.x0 <= bounds.x1
+        && bounds.y0 <= visible.y1
+        && visible.y0 <= bounds.y1
+}
+
+// How far a stroke can reach past its path, miter joins can reach past half the width
+fn stroke_reach(style: &vello::kurbo::Stroke) -> f64 {
+    style.width.max(style.width * style.miter_limit) / 2.0
+}
+
+fn stroke_contains(shape: &impl Shape, style: &vello::kurbo::Stroke, point: Point) -> bool {
+    // Cheap rejection before expanding the stroke
+    let reach = stroke_reach(style);
+    if !shape.bounding_box().inflate(reach, reach).contains(point) {
+        return false;
+    }
+    vello::kurbo::stroke(
+        shape.path_elements(0.1),
+        style,
+        &Default::default(),
+        0.1,
+    )
+    .contains(point)
 }
 
 #[derive(Component)]
@@ -88,9 +352,83 @@ impl Module for VelloShapeModule {
     fn module(world: &World) {
         world.module::<Self>("module");
 
+        // Refreshes the subtrees under transforms that changed, after the update so that
+        // shapes are picked and drawn where they were moved to. The write is declared so
+        // that flecs merges it before the systems in `PreStore` and `OnStore` read it.
+        system!("propagate_transforms", world, &Transform)
+            .write::<GlobalTransform>()
+            .kind::<flecs::pipeline::PostUpdate>()
+            .detect_changes()
+            .run(|mut it| {
+                while it.next() {
+                    if !it.is_changed() {
+                        continue;
+                    }
+                    for i in it.iter() {
+                        refresh(it.entity(i));
+                    }
+                }
+            });
+
+        // Moving an entity to another parent moves it without changing its `Transform`
+        observer!("reparent_transforms", world, flecs::OnAdd, (flecs::ChildOf, *))
+            .each_entity(|e, _| refresh(e));
+
+        // Without a `Transform` an entity is no longer placed, its children move into the
+        // space of its parent
+        observer!("remove_transforms", world, flecs::OnRemove, &Transform).each_entity(|e, _| {
+            if e.has::<GlobalTransform>() {
+                e.remove::<GlobalTransform>();
+                invalidate_scene(e);
+            }
+            if let Some(space) = parent_space(e) {
+                propagate(e, space);
+            }
+        });
+
+        // A z set on a group applies to the shapes under it, so any change to one
+        // re-encodes the scene
+        system!(world, &ZIndex)
+            .kind::<flecs::pipeline::PreStore>()
+            .detect_changes()
+            .run(|mut it| {
+                while it.next() {
+                    if !it.is_changed() {
+                        continue;
+                    }
+                    for i in it.iter() {
+                        invalidate_scene(it.entity(i));
+                    }
+                }
+            });
+
+        observer!(world, flecs::OnRemove, &ZIndex).each_entity(|e, _| invalidate_scene(e));
+
+        world.set(ShapeTypes::default());
+
         Circle::systems(world);
         Rect::systems(world);
         Line::systems(world);
+        Ellipse::systems(world);
+        RoundedRect::systems(world);
+        Polygon::systems(world);
+        ArcShape::systems(world);
+        Pie::systems(world);
+        QuadCurve::systems(world);
+        CubicCurve::systems(world);
+        PathShape::systems(world);
+
+        // Ties are broken by entity id so that they paint the same way every frame
+        system!("encode_shapes", world, &mut VelloScene)
+            .kind::<PreRender>()
+            .each_iter(|it, _, scene| {
+                let world = it.world();
+                let mut draw_list = std::mem::take(&mut scene.draw_list);
+                draw_list.sort_by_key(|item| (item.z, item.entity));
+                for item in draw_list {
+                    (item.draw)(item.entity.entity_view(world), scene);
+                }
+            });
     }
 }
 
@@ -151,13 +489,269 @@ impl VelloShape for Line {
         vello::kurbo::Line::new((0.0, 0.0), (self.x, self.y))
     }
 
-    fn fill(&self, scene: &mut VelloScene, fill: &Fill, transform: impl Into<Affine>) {
-        scene.stroke(
-            &vello::kurbo::Stroke::new(10.0),
-            transform.into(),
-            fill.color,
-            None,
-            &self.shape(),
+    fn fill_stroke(&self) -> Option<vello::kurbo::Stroke> {
+        Some(vello::kurbo::Stroke::new(OPEN_FILL_WIDTH))
+    }
+}
+
+// Width an open shape's fill is painted with
+const OPEN_FILL_WIDTH: f64 = 10.0;
+
+#[derive(Component)]
+pub struct Ellipse {
+    pub radius_x: f64,
+    pub radius_y: f64,
+}
+
+impl Ellipse {
+    pub fn new(radius_x: f64, radius_y: f64) -> Self {
+        Self { radius_x, radius_y }
+    }
+}
+
+impl VelloShape for Ellipse {
+    fn shape(&self) -> impl vello::kurbo::Shape {
+        vello::kurbo::Ellipse::new((0.0, 0.0), (self.radius_x, self.radius_y), 0.0)
+    }
+}
+
+#[derive(Component)]
+pub struct RoundedRect {
+    pub width: f64,
+    pub height: f64,
+    pub radii: vello::kurbo::RoundedRectRadii,
+}
+
+impl RoundedRect {
+    pub fn new(width: f64, height: f64, radius: f64) -> Self {
+        Self {
+            width,
+            height,
+            radii: radius.into(),
+        }
+    }
+
+    // Radii clockwise from the top left corner
+    pub fn with_radii(
+        width: f64,
+        height: f64,
+        top_left: f64,
+        top_right: f64,
+        bottom_right: f64,
+        bottom_left: f64,
+    ) -> Self {
+        Self {
+            width,
+            height,
+            radii: vello::kurbo::RoundedRectRadii::new(
+                top_left,
+                top_right,
+                bottom_right,
+                bottom_left,
+            ),
+        }
+    }
+}
+
+impl VelloShape for RoundedRect {
+    fn shape(&self) -> impl vello::kurbo::Shape {
+        let rect = vello::kurbo::Rect::new(
+            -self.width / 2.0,
+            -self.height / 2.0,
+            self.width / 2.0,
+            self.height / 2.0,
         );
+        vello::kurbo::RoundedRect::from_rect(rect, self.radii)
+    }
+}
+
+// A closed polygon through `points`
+#[derive(Component)]
+pub struct Polygon {
+    pub points: Vec<Point>,
+}
+
+impl Polygon {
+    pub fn new(points: impl IntoIterator<Item = impl Into<Point>>) -> Self {
+        Self {
+            points: points.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    // `sides` corners evenly spaced on a circle, the first one pointing up
+    pub fn regular(sides: usize, radius: f64) -> Self {
+        let sides = sides.max(3);
+        Self::new((0..sides).map(|i| {
+            let angle = std::f64::consts::TAU * i as f64 / sides as f64
+                - std::f64::consts::FRAC_PI_2;
+            Point::new(radius * angle.cos(), radius * angle.sin())
+        }))
+    }
+}
+
+impl VelloShape for Polygon {
+    fn shape(&self) -> impl vello::kurbo::Shape {
+        let mut path = vello::kurbo::BezPath::new();
+        let mut points = self.points.iter();
+        if let Some(first) = points.next() {
+            path.move_to(*first);
+            for point in points {
+                path.line_to(*point);
+            }
+            path.close_path();
+        }
+        path
+    }
+}
+
+// An open arc of a circle, angles are in radians clockwise from the positive x axis
+#[derive(Component)]
+pub struct ArcShape {
+    pub radius: f64,
+    pub start_angle: f64,
+    pub sweep_angle: f64,
+}
+
+impl ArcShape {
+    pub fn new(radius: f64, start_angle: f64, sweep_angle: f64) -> Self {
+        Self {
+            radius,
+            start_angle,
+            sweep_angle,
+        }
+    }
+}
+
+impl VelloShape for ArcShape {
+    fn shape(&self) -> impl vello::kurbo::Shape {
+        vello::kurbo::Arc::new(
+            (0.0, 0.0),
+            (self.radius, self.radius),
+            self.start_angle,
+            self.sweep_angle,
+            0.0,
+        )
+    }
+
+    fn fill_stroke(&self) -> Option<vello::kurbo::Stroke> {
+        Some(vello::kurbo::Stroke::new(OPEN_FILL_WIDTH))
+    }
+}
+
+// A pie slice, or a slice of a ring when `inner_radius` is above 0. Angles are as
+// for `ArcShape`.
+#[derive(Component)]
+pub struct Pie {
+    pub radius: f64,
+    pub inner_radius: f64,
+    pub start_angle: f64,
+    pub sweep_angle: f64,
+}
+
+impl Pie {
+    pub fn new(radius: f64, start_angle: f64, sweep_angle: f64) -> Self {
+        Self {
+            radius,
+            inner_radius: 0.0,
+            start_angle,
+            sweep_angle,
+        }
+    }
+}
+
+impl VelloShape for Pie {
+    fn shape(&self) -> impl vello::kurbo::Shape {
+        vello::kurbo::CircleSegment::new(
+            (0.0, 0.0),
+            self.radius,
+            self.inner_radius,
+            self.start_angle,
+            self.sweep_angle,
+        )
+    }
+}
+
+// A quadratic curve from the origin to `end`
+#[derive(Component)]
+pub struct QuadCurve {
+    pub control: Point,
+    pub end: Point,
+}
+
+impl QuadCurve {
+    pub fn new(control: impl Into<Point>, end: impl Into<Point>) -> Self {
+        Self {
+            control: control.into(),
+            end: end.into(),
+        }
+    }
+}
+
+impl VelloShape for QuadCurve {
+    fn shape(&self) -> impl vello::kurbo::Shape {
+        vello::kurbo::PathSeg::Quad(vello::kurbo::QuadBez::new(
+            Point::ZERO,
+            self.control,
+            self.end,
+        ))
+    }
+
+    fn fill_stroke(&self) -> Option<vello::kurbo::Stroke> {
+        Some(vello::kurbo::Stroke::new(OPEN_FILL_WIDTH))
+    }
+}
+
+// A cubic curve from the origin to `end`
+#[derive(Component)]
+pub struct CubicCurve {
+    pub control1: Point,
+    pub control2: Point,
+    pub end: Point,
+}
+
+impl CubicCurve {
+    pub fn new(
+        control1: impl Into<Point>,
+        control2: impl Into<Point>,
+        end: impl Into<Point>,
+    ) -> Self {
+        Self {
+            control1: control1.into(),
+            control2: control2.into(),
+            end: end.into(),
+        }
+    }
+}
+
+impl VelloShape for CubicCurve {
+    fn shape(&self) -> impl vello::kurbo::Shape {
+        vello::kurbo::PathSeg::Cubic(vello::kurbo::CubicBez::new(
+            Point::ZERO,
+            self.control1,
+            self.control2,
+            self.end,
+        ))
+    }
+
+    fn fill_stroke(&self) -> Option<vello::kurbo::Stroke> {
+        Some(vello::kurbo::Stroke::new(OPEN_FILL_WIDTH))
+    }
+}
+
+// Any path, open subpaths are closed when filled
+#[derive(Component)]
+pub struct PathShape {
+    pub path: vello::kurbo::BezPath,
+}
+
+impl PathShape {
+    pub fn new(path: vello::kurbo::BezPath) -> Self {
+        Self { path }
+    }
+}
+
+impl VelloShape for PathShape {
+    fn shape(&self) -> impl vello::kurbo::Shape {
+        self.path.elements()
     }
 }
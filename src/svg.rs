@@ -0,0 +1,296 @@
+use std::{fmt::Write, path::Path};
+
+use flecs_ecs::prelude::*;
+use vello::{
+    kurbo::{Affine, Cap, Join, Shape},
+    peniko::{Brush, Color, Extend, GradientKind},
+};
+
+use crate::{
+    shape::{z_index, ShapeTypes},
+    Fill, GlobalTransform, Stroke, VelloScene, VelloShape,
+};
+
+// Add to a scene to export its shapes to `<scene name>.svg`, removed once written.
+// A tag so that it can be added through the flecs Rest endpoint.
+#[derive(Component)]
+pub struct ExportSvg;
+
+// Writes one entity's shape into the document if it has shape type `T`
+pub(crate) type Exporter = fn(EntityView, &mut SvgDocument);
+
+pub(crate) fn export_shape<T: VelloShape>(entity: EntityView, doc: &mut SvgDocument) {
+    entity.try_get::<(&T, &GlobalTransform, Option<&Fill>, Option<&Stroke>)>(
+        |(shape, transform, fill, stroke)| doc.shape(shape, **transform, fill, stroke),
+    );
+}
+
+// Builds an SVG document from the shapes under a scene, in world space so the camera
+// is not applied. The view box is fitted to the shapes.
+pub fn scene_to_svg(scene: EntityView) -> String {
+    let mut entities = Vec::new();
+    collect_children(scene, &mut entities);
+    // In the order the shapes are painted
+    entities.sort();
+
+    let mut doc = SvgDocument::default();
+    let world = scene.world();
+    world.get::<&ShapeTypes>(|types| {
+        for (_, entity) in entities {
+            for export in &types.exporters {
+                export(entity.entity_view(world), &mut doc);
+            }
+        }
+    });
+    let base_color = scene.get::<&VelloScene>(|scene| scene.base_color);
+    doc.finish(base_color)
+}
+
+pub fn export_svg(scene: EntityView, path: impl AsRef<Path>) -> std::io::Result<()> {
+    std::fs::write(path, scene_to_svg(scene))
+}
+
+fn collect_children(entity: EntityView, entities: &mut Vec<(i32, Entity)>) {
+    entity.each_child(|child| {
+        // Nested scenes are exported on their own
+        if child.has::<VelloScene>() {
+            return;
+        }
+        entities.push((z_index(child), child.id()));
+        collect_children(child, entities);
+    });
+}
+
+const TOLERANCE: f64 = 0.1;
+
+#[derive(Default)]
+pub(crate) struct SvgDocument {
+    body: String,
+    // Gradients referenced from the body
+    defs: String,
+    gradients: usize,
+    bounds: Option<vello::kurbo::Rect>,
+}
+
+impl SvgDocument {
+    pub(crate) fn shape<T: VelloShape>(
+        &mut self,
+        shape: &T,
+        transform: Affine,
+        fill: Option<&Fill>,
+        stroke: Option<&Stroke>,
+    ) {
+        let path = shape.shape().to_path(TOLERANCE);
+        let data = path.to_svg();
+
+        // Open shapes paint their fill as a stroke
+        let fill_stroke = shape.fill_stroke();
+        if let (Some(fill), Some(style)) = (fill, &fill_stroke) {
+            let paint = self.brush("stroke", &fill.brush, fill.brush_transform);
+            let attributes = format!(" fill=\"none\"{}", stroke_attributes(style, &paint));
+            self.element(&data, transform, &attributes);
+            self.include(&path, transform, style.width);
+        }
+
+        let mut attributes = match fill {
+            Some(fill) if fill_stroke.is_none() => {
+                let paint = self.brush("fill", &fill.brush, fill.brush_transform);
+                fill_attributes(fill, &paint)
+            }
+            _ => " fill=\"none\"".to_string(),
+        };
+        let closed_fill = fill.is_some() && fill_stroke.is_none();
+        if let Some(stroke) = stroke {
+            let paint = self.brush("stroke", &stroke.brush, stroke.brush_transform);
+            attributes.push_str(&stroke_attributes(&stroke.style, &paint));
+        }
+        if closed_fill || stroke.is_some() {
+            self.element(&data, transform, &attributes);
+            self.include(&path, transform, stroke.map_or(0.0, |stroke| stroke.style.width));
+        }
+    }
+
+    // Paint attribute for a brush. Gradients are added to the defs in the shape's local
+    // space, which is the user space of the path element that references them.
+    fn brush(
+        &mut self,
+        attribute: &str,
+        brush: &Brush,
+        brush_transform: Option<Affine>,
+    ) -> String {
+        let gradient = match brush {
+            Brush::Solid(color) => return paint(attribute, *color),
+            Brush::Gradient(gradient) => gradient,
+            // Images are not embedded
+            Brush::Image(_) => return format!(" {attribute}=\"none\""),
+        };
+        let (element, geometry) = match gradient.kind {
+            GradientKind::Linear { start, end } => (
+                "linearGradient",
+                format!(
+                    " x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\"",
+                    start.x, start.y, end.x, end.y
+                ),
+            ),
+            GradientKind::Radial {
+                start_center,
+                start_radius,
+                end_center,
+                end_radius,
+            } => (
+                "radialGradient",
+                format!(
+                    " cx=\"{}\" cy=\"{}\" r=\"{end_radius}\" fx=\"{}\" fy=\"{}\" fr=\"{start_radius}\"",
+                    end_center.x, end_center.y, start_center.x, start_center.y
+                ),
+            ),
+            // SVG has no sweep gradients, the first stop stands in for one
+            GradientKind::Sweep { .. } => {
+                let color = gradient
+                    .stops
+                    .first()
+                    .map_or(Color::TRANSPARENT, |stop| stop.color);
+                return paint(attribute, color);
+            }
+        };
+        let spread = match gradient.extend {
+            Extend::Pad => "pad",
+            Extend::Repeat => "repeat",
+            Extend::Reflect => "reflect",
+        };
+
+        let id = format!("gradient{}", self.gradients);
+        self.gradients += 1;
+        let _ = write!(
+            self.defs,
+            "    <{element} id=\"{id}\" gradientUnits=\"userSpaceOnUse\" spreadMethod=\"{spread}\"{geometry}"
+        );
+        if let Some(transform) = brush_transform {
+            let [a, b, c, d, e, f] = transform.as_coeffs();
+            let _ = write!(self.defs, " gradientTransform=\"matrix({a} {b} {c} {d} {e} {f})\"");
+        }
+        self.defs.push_str(">\n");
+        for stop in gradient.stops.iter() {
+            let color = stop.color;
+            let _ = writeln!(
+                self.defs,
+                "      <stop offset=\"{}\" stop-color=\"#{:02x}{:02x}{:02x}\" stop-opacity=\"{}\"/>",
+                stop.offset,
+                color.r,
+                color.g,
+                color.b,
+                color.a as f64 / 255.0
+            );
+        }
+        let _ = writeln!(self.defs, "    </{element}>");
+        format!(" {attribute}=\"url(#{id})\"")
+    }
+
+    fn element(&mut self, data: &str, transform: Affine, attributes: &str) {
+        let [a, b, c, d, e, f] = transform.as_coeffs();
+        let _ = writeln!(
+            self.body,
+            "  <path d=\"{data}\" transform=\"matrix({a} {b} {c} {d} {e} {f})\"{attributes}/>"
+        );
+    }
+
+    fn include(&mut self, path: &vello::kurbo::BezPath, transform: Affine, stroke_width: f64) {
+        let reach = stroke_width / 2.0;
+        let bounds = transform.transform_rect_bbox(path.bounding_box().inflate(reach, reach));
+        self.bounds = Some(match self.bounds {
+            Some(current) => current.union(bounds),
+            None => bounds,
+        });
+    }
+
+    fn finish(self, background: Color) -> String {
+        let bounds = self.bounds.unwrap_or_default();
+        let (x, y, width, height) = (bounds.x0, bounds.y0, bounds.width(), bounds.height());
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"{x} {y} {width} {height}\">"
+        );
+        let _ = writeln!(
+            svg,
+            "  <rect x=\"{x}\" y=\"{y}\" width=\"{width}\" height=\"{height}\"{}/>",
+            paint("fill", background)
+        );
+        if !self.defs.is_empty() {
+            svg.push_str("  <defs>\n");
+            svg.push_str(&self.defs);
+            svg.push_str("  </defs>\n");
+        }
+        svg.push_str(&self.body);
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+fn paint(attribute: &str, color: Color) -> String {
+    let mut paint = format!(
+        " {attribute}=\"#{:02x}{:02x}{:02x}\"",
+        color.r, color.g, color.b
+    );
+    if color.a != 255 {
+        let _ = write!(paint, " {attribute}-opacity=\"{}\"", color.a as f64 / 255.0);
+    }
+    paint
+}
+
+fn fill_attributes(fill: &Fill, paint: &str) -> String {
+    let rule = match fill.style {
+        vello::peniko::Fill::NonZero => "nonzero",
+        vello::peniko::Fill::EvenOdd => "evenodd",
+    };
+    format!("{paint} fill-rule=\"{rule}\"")
+}
+
+fn stroke_attributes(style: &vello::kurbo::Stroke, paint: &str) -> String {
+    let mut attributes = format!("{paint} stroke-width=\"{}\"", style.width);
+    // SVG has a single cap for both ends
+    let cap = match style.start_cap {
+        Cap::Butt => "butt",
+        Cap::Square => "square",
+        Cap::Round => "round",
+    };
+    let join = match style.join {
+        Join::Bevel => "bevel",
+        Join::Miter => "miter",
+        Join::Round => "round",
+    };
+    let _ = write!(
+        attributes,
+        " stroke-linecap=\"{cap}\" stroke-linejoin=\"{join}\" stroke-miterlimit=\"{}\"",
+        style.miter_limit
+    );
+    if !style.dash_pattern.is_empty() {
+        let dashes: Vec<String> = style.dash_pattern.iter().map(f64::to_string).collect();
+        let _ = write!(
+            attributes,
+            " stroke-dasharray=\"{}\" stroke-dashoffset=\"{}\"",
+            dashes.join(" "),
+            style.dash_offset
+        );
+    }
+    attributes
+}
+
+#[derive(Component)]
+pub struct SvgModule;
+
+impl Module for SvgModule {
+    fn module(world: &World) {
+        world.module::<Self>("module");
+
+        observer!("export_svg", world, flecs::OnAdd, ExportSvg, &VelloScene).each_entity(
+            |e, _| {
+                let path = format!("{}.svg", e.get_name().unwrap_or_else(|| "scene".to_string()));
+                if let Err(err) = export_svg(e, &path) {
+                    eprintln!("Failed to export {path}: {err}");
+                }
+                e.remove::<ExportSvg>();
+            },
+        );
+    }
+}
@@ -12,6 +12,8 @@ use vello::Scene;
 pub struct TextWriter {
     font_cx: FontContext,
     layout_cx: LayoutContext<Color>,
+    // Glyph runs drawn since the render stats last took them
+    glyph_runs: u32,
 }
 
 impl TextWriter {
@@ -20,9 +22,14 @@ impl TextWriter {
         Self {
             font_cx: FontContext::default(),
             layout_cx: LayoutContext::new(),
+            glyph_runs: 0,
         }
     }
 
+    pub(crate) fn take_glyph_runs(&mut self) -> u32 {
+        std::mem::take(&mut self.glyph_runs)
+    }
+
     pub fn add(
         &mut self,
         scene: &mut Scene,
@@ -48,6 +55,7 @@ impl TextWriter {
                         let run = glyph_run.run();
                         let style = glyph_run.style();
                         let font = run.font();
+                        self.glyph_runs += 1;
 
                         scene
                             .draw_glyphs(font)
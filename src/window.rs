@@ -1,22 +1,51 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
 use deref_derive::{Deref, DerefMut};
 use flecs_ecs::prelude::*;
-use wgpu::{Surface, SurfaceConfiguration, SurfaceTexture, TextureView};
+use wgpu::{
+    Device, PresentMode, Surface, SurfaceConfiguration, SurfaceTexture, Texture, TextureFormat,
+    TextureView,
+};
 use winit::window::WindowId;
 
+use crate::WGPU;
+
 #[derive(Component, Default, Deref, DerefMut)]
 pub struct WindowMap(HashMap<WindowId, Entity>);
 
+// Presentation settings for a window's surface. Set as a singleton before the
+// application initializes to configure the first window, or set on a window entity
+// to reconfigure its surface at runtime.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct SurfaceSettings {
+    pub present_mode: PresentMode,
+    pub desired_maximum_frame_latency: u32,
+    // Present through an `Rgba16Float` surface where the surface supports it
+    pub hdr: bool,
+}
+
+impl Default for SurfaceSettings {
+    fn default() -> Self {
+        Self {
+            present_mode: PresentMode::AutoVsync,
+            desired_maximum_frame_latency: 2,
+            hdr: false,
+        }
+    }
+}
+
 #[derive(Component, Deref, DerefMut)]
 pub struct Window {
     #[deref]
     pub(crate) window: winit::window::Window,
-    pub(crate) surface: Surface<'static>,
+    pub(crate) surface: Arc<Surface<'static>>,
     pub(crate) config: SurfaceConfiguration,
     pub(crate) redraw: bool,
     pub(crate) texture: Option<SurfaceTexture>,
     pub(crate) view: Option<TextureView>,
+    pub(crate) screenshots: Vec<PathBuf>,
+    // Whether the render thread owns the surface's frames
+    pub(crate) pipelined: bool,
 }
 
 impl Window {
@@ -27,4 +56,53 @@ impl Window {
     pub fn height(&self) -> u32 {
         self.config.height
     }
+
+    pub fn format(&self) -> TextureFormat {
+        self.config.format
+    }
+
+    // The frame being rendered, only available during the render phases
+    pub fn texture(&self) -> Option<&Texture> {
+        self.texture.as_ref().map(|frame| &frame.texture)
+    }
+
+    // Cleared when acquired, anything drawn into it in `PreRender` shows through where
+    // vello's output is transparent
+    pub fn view(&self) -> Option<&TextureView> {
+        self.view.as_ref()
+    }
+
+    pub(crate) fn configure(&mut self, wgpu: &WGPU, settings: &SurfaceSettings) {
+        let capabilities = self.surface.get_capabilities(&wgpu.adapter);
+        self.config.format = surface_format(&capabilities.formats, settings.hdr);
+        self.config.present_mode = match settings.present_mode {
+            mode @ (PresentMode::AutoVsync | PresentMode::AutoNoVsync) => mode,
+            mode if capabilities.present_modes.contains(&mode) => mode,
+            // Fifo is supported everywhere
+            _ => PresentMode::Fifo,
+        };
+        self.config.desired_maximum_frame_latency = settings.desired_maximum_frame_latency;
+        self.configure_surface(&wgpu.device);
+    }
+
+    // In pipelined mode the render thread applies the config with the next frame, as the
+    // surface cannot be configured while it holds one of its textures
+    pub(crate) fn configure_surface(&self, device: &Device) {
+        if !self.pipelined {
+            self.surface.configure(device, &self.config);
+        }
+    }
+}
+
+// Prefers the formats vello renders in so that the blit is a plain copy, then sRGB,
+// then whatever else the surface offers
+fn surface_format(formats: &[TextureFormat], hdr: bool) -> TextureFormat {
+    if hdr && formats.contains(&TextureFormat::Rgba16Float) {
+        return TextureFormat::Rgba16Float;
+    }
+    let find = |predicate: fn(&TextureFormat) -> bool| formats.iter().copied().find(predicate);
+    find(|it| matches!(it, TextureFormat::Rgba8Unorm | TextureFormat::Bgra8Unorm))
+        .or_else(|| find(|it| it.is_srgb()))
+        .or_else(|| formats.first().copied())
+        .expect("surface should support at least one format")
 }
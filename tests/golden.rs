@@ -0,0 +1,466 @@
+//! Golden image tests. Each test renders one frame of a scene on the fallback adapter
+//! into a headless target and compares it against `tests/golden/<name>.png`.
+//!
+//! A missing reference fails the test, run with `BLESS=1` to record new references or
+//! update existing ones. On failure the actual frame and a diff are written to the test
+//! temp directory, mismatched pixels are red in the diff.
+//!
+//! Without a fallback adapter the tests fail too, unless `GOLDEN_SKIP_NO_ADAPTER=1` is
+//! set for machines that cannot render at all.
+
+use std::{fs::File, io::BufWriter, path::Path};
+
+use flecs_ecs::prelude::*;
+use flecs_wgpu_demo::*;
+use vello::{kurbo::Affine, peniko::Color};
+
+const WIDTH: u32 = 128;
+const HEIGHT: u32 = 128;
+// Largest difference allowed in any channel, adapters rasterize edges slightly differently
+const TOLERANCE: u8 = 8;
+
+struct Harness {
+    world: World,
+    target: Entity,
+    scene: Entity,
+}
+
+impl Harness {
+    // `None` when there is no fallback adapter to render on and skipping was asked for
+    fn new() -> Option<Self> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: true,
+            compatible_surface: None,
+        }));
+        if adapter.is_none() {
+            assert!(
+                std::env::var_os("GOLDEN_SKIP_NO_ADAPTER").is_some(),
+                "No fallback adapter available, set GOLDEN_SKIP_NO_ADAPTER=1 to skip golden tests."
+            );
+            eprintln!("No fallback adapter available, skipping golden test.");
+            return None;
+        }
+        let wgpu = pollster::block_on(WGPU::new(instance, None, RenderBackend::Fallback));
+        let target = RenderTarget::new(&wgpu.device, WIDTH, HEIGHT);
+
+        let world = World::new();
+        world.set(wgpu);
+        world.import::<RenderModule>();
+        world.import::<VelloShapeModule>();
+
+        let target = world.entity_named("target").set(target).id();
+        let mut scene = VelloScene::default();
+        scene.base_color = Color::BLACK;
+        let scene = world
+            .entity_named("scene")
+            .child_of_id(target)
+            .set(scene)
+            .id();
+        Some(Self {
+            world,
+            target,
+            scene,
+        })
+    }
+
+    fn spawn(&self) -> EntityView {
+        self.world.entity().child_of_id(self.scene)
+    }
+
+    // Renders the shapes spawned so far in a single frame
+    fn render(&self) -> Vec<u8> {
+        self.world.progress();
+        let encoded = self
+            .scene
+            .entity_view(&self.world)
+            .get::<&SceneStats>(|stats| stats.shapes);
+        assert!(encoded > 0, "no shapes were encoded in the first frame");
+        assert_eq!(
+            self.world.get::<&RenderStats>(|stats| stats.shapes_encoded),
+            encoded
+        );
+        self.world.get::<&WGPU>(|wgpu| {
+            self.target
+                .entity_view(&self.world)
+                .get::<&RenderTarget>(|target| read_texture(wgpu, target.texture()))
+        })
+    }
+}
+
+fn read_texture(wgpu: &WGPU, texture: &wgpu::Texture) -> Vec<u8> {
+    let (width, height) = (texture.width(), texture.height());
+    let padded_bytes_per_row = (width * 4).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let buffer = wgpu.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("golden"),
+        size: (padded_bytes_per_row * height) as u64,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = wgpu
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("golden"),
+        });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: Some(height),
+            },
+        },
+        texture.size(),
+    );
+    wgpu.queue.submit(Some(encoder.finish()));
+
+    buffer
+        .slice(..)
+        .map_async(wgpu::MapMode::Read, |result| result.expect("Failed to map readback."));
+    wgpu.device.poll(wgpu::Maintain::Wait);
+
+    let mapped = buffer.slice(..).get_mapped_range();
+    mapped
+        .chunks(padded_bytes_per_row as usize)
+        .flat_map(|row| &row[..(width * 4) as usize])
+        .copied()
+        .collect()
+}
+
+fn assert_golden(name: &str, actual: &[u8]) {
+    let reference_path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{name}.png"));
+    if std::env::var_os("BLESS").is_some() {
+        std::fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        write_png(&reference_path, actual);
+        eprintln!("Recorded {}", reference_path.display());
+        return;
+    }
+    assert!(
+        reference_path.exists(),
+        "{name}: no reference at {}, run with BLESS=1 to record it",
+        reference_path.display()
+    );
+
+    let reference = read_png(&reference_path);
+    assert_eq!(
+        reference.len(),
+        actual.len(),
+        "{name}: reference is not {WIDTH}x{HEIGHT}"
+    );
+
+    let mut mismatched = 0;
+    let mut diff = Vec::with_capacity(actual.len());
+    for (actual, reference) in actual.chunks_exact(4).zip(reference.chunks_exact(4)) {
+        let delta = actual
+            .iter()
+            .zip(reference)
+            .map(|(actual, reference)| actual.abs_diff(*reference))
+            .max()
+            .unwrap_or(0);
+        if delta > TOLERANCE {
+            mismatched += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            // The rest of the frame is dimmed so that mismatches stand out
+            let luma = (actual[0] as u32 + actual[1] as u32 + actual[2] as u32) / 12;
+            diff.extend_from_slice(&[luma as u8, luma as u8, luma as u8, 255]);
+        }
+    }
+
+    if mismatched > 0 {
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        std::fs::create_dir_all(&dir).unwrap();
+        write_png(&dir.join(format!("{name}.actual.png")), actual);
+        write_png(&dir.join(format!("{name}.diff.png")), &diff);
+        panic!(
+            "{name}: {mismatched} pixels differ by more than {TOLERANCE}, see {}",
+            dir.display()
+        );
+    }
+}
+
+fn write_png(path: &Path, data: &[u8]) {
+    let file = BufWriter::new(File::create(path).unwrap());
+    let mut encoder = png::Encoder::new(file, WIDTH, HEIGHT);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(data)
+        .unwrap();
+}
+
+fn read_png(path: &Path) -> Vec<u8> {
+    let decoder = png::Decoder::new(File::open(path).unwrap());
+    let mut reader = decoder.read_info().unwrap();
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data).unwrap();
+    assert_eq!(
+        (info.color_type, info.bit_depth),
+        (png::ColorType::Rgba, png::BitDepth::Eight),
+        "{} is not 8-bit RGBA",
+        path.display()
+    );
+    data.truncate(info.buffer_size());
+    data
+}
+
+#[test]
+fn fills() {
+    let Some(harness) = Harness::new() else {
+        return;
+    };
+    harness
+        .spawn()
+        .set(Transform::translate(32.0, 32.0))
+        .set(Fill::new(Color::RED))
+        .set(Circle::new(24.0));
+    harness
+        .spawn()
+        .set(Transform::translate(96.0, 32.0))
+        .set(Fill::new(Color::LIME))
+        .set(Rect::new(48.0, 32.0));
+    harness
+        .spawn()
+        .set(Transform::translate(16.0, 96.0))
+        .set(Fill::new(Color::BLUE))
+        .set(Line::new(96.0, 16.0));
+
+    assert_golden("fills", &harness.render());
+}
+
+#[test]
+fn strokes() {
+    let Some(harness) = Harness::new() else {
+        return;
+    };
+    harness
+        .spawn()
+        .set(Transform::translate(40.0, 40.0))
+        .set(Fill::new(Color::YELLOW))
+        .set(Stroke::new(6.0, Color::WHITE))
+        .set(Circle::new(28.0));
+
+    let mut dashed = Stroke::new(4.0, Color::AQUA);
+    dashed.style = dashed.style.with_dashes(0.0, [8.0, 4.0]);
+    harness
+        .spawn()
+        .set(Transform::translate(88.0, 88.0))
+        .set(dashed)
+        .set(Rect::new(56.0, 56.0));
+
+    assert_golden("strokes", &harness.render());
+}
+
+#[test]
+fn transforms() {
+    let Some(harness) = Harness::new() else {
+        return;
+    };
+    let transform = Affine::translate((64.0, 64.0))
+        * Affine::rotate(std::f64::consts::FRAC_PI_4)
+        * Affine::scale_non_uniform(1.5, 0.5);
+    harness
+        .spawn()
+        .set(Transform(transform))
+        .set(Fill::new(Color::FUCHSIA))
+        .set(Rect::new(64.0, 64.0));
+
+    assert_golden("transforms", &harness.render());
+}
+
+#[test]
+fn camera_and_culling() {
+    let Some(harness) = Harness::new() else {
+        return;
+    };
+    harness
+        .scene
+        .entity_view(&harness.world)
+        .get::<&mut VelloScene>(|scene| scene.camera = Affine::translate((-64.0, 0.0)));
+    // On screen once the camera is applied
+    harness
+        .spawn()
+        .set(Transform::translate(128.0, 64.0))
+        .set(Fill::new(Color::ORANGE))
+        .set(Circle::new(32.0));
+    // Off screen and culled
+    harness
+        .spawn()
+        .set(Transform::translate(0.0, 64.0))
+        .set(Fill::new(Color::WHITE))
+        .set(Circle::new(16.0));
+
+    assert_golden("camera_and_culling", &harness.render());
+    let culled = harness
+        .scene
+        .entity_view(&harness.world)
+        .get::<&VelloScene>(|scene| scene.culled());
+    assert_eq!(culled, 1);
+}
+
+#[test]
+fn hierarchy() {
+    let Some(harness) = Harness::new() else {
+        return;
+    };
+    // An arm of two segments, each child is placed relative to its parent
+    let shoulder = harness
+        .spawn()
+        .set(Transform(
+            Affine::translate((24.0, 104.0)) * Affine::rotate(-std::f64::consts::FRAC_PI_4),
+        ))
+        .set(Fill::new(Color::WHITE))
+        .set(Circle::new(8.0));
+    let elbow = harness
+        .world
+        .entity()
+        .child_of_id(shoulder)
+        .set(Transform(
+            Affine::translate((56.0, 0.0)) * Affine::rotate(std::f64::consts::FRAC_PI_2),
+        ))
+        .set(Fill::new(Color::RED))
+        .set(Circle::new(6.0));
+    harness
+        .world
+        .entity()
+        .child_of_id(shoulder)
+        .set(Transform::translate(28.0, 0.0))
+        .set(Fill::new(Color::BLUE))
+        .set(Rect::new(48.0, 8.0));
+    harness
+        .world
+        .entity()
+        .child_of_id(elbow)
+        .set(Transform::translate(24.0, 0.0))
+        .set(Fill::new(Color::LIME))
+        .set(Rect::new(40.0, 6.0));
+
+    assert_golden("hierarchy", &harness.render());
+}
+
+#[test]
+fn z_index() {
+    let Some(harness) = Harness::new() else {
+        return;
+    };
+    // Spawned first but raised above the rect
+    harness
+        .spawn()
+        .set(ZIndex(1))
+        .set(Transform::translate(48.0, 64.0))
+        .set(Fill::new(Color::RED))
+        .set(Stroke::new(4.0, Color::WHITE))
+        .set(Circle::new(32.0));
+    harness
+        .spawn()
+        .set(Transform::translate(80.0, 64.0))
+        .set(Fill::new(Color::BLUE))
+        .set(Rect::new(64.0, 64.0));
+    // Inherits the group's z, so it paints over the circle
+    let group = harness.spawn().set(ZIndex(2));
+    harness
+        .world
+        .entity()
+        .child_of_id(group)
+        .set(Transform::translate(64.0, 64.0))
+        .set(Fill::new(Color::LIME))
+        .set(Line::new(0.0, 96.0));
+
+    assert_golden("z_index", &harness.render());
+}
+
+#[test]
+fn shapes() {
+    let Some(harness) = Harness::new() else {
+        return;
+    };
+    let shapes: [(f64, f64, Color, fn(EntityView) -> EntityView); 8] = [
+        (20.0, 20.0, Color::RED, |e| e.set(Ellipse::new(16.0, 8.0))),
+        (52.0, 20.0, Color::LIME, |e| {
+            e.set(RoundedRect::with_radii(24.0, 20.0, 0.0, 4.0, 8.0, 2.0))
+        }),
+        (84.0, 20.0, Color::BLUE, |e| e.set(Polygon::regular(6, 12.0))),
+        (112.0, 20.0, Color::YELLOW, |e| {
+            e.set(Polygon::new([(0.0, -12.0), (8.0, 12.0), (-10.0, 0.0)]))
+        }),
+        (20.0, 72.0, Color::AQUA, |e| {
+            e.set(ArcShape::new(12.0, 0.0, std::f64::consts::PI * 1.5))
+        }),
+        (52.0, 72.0, Color::FUCHSIA, |e| {
+            e.set(Pie::new(14.0, 0.0, std::f64::consts::FRAC_PI_2 * 3.0))
+        }),
+        (76.0, 72.0, Color::ORANGE, |e| {
+            e.set(QuadCurve::new((12.0, -24.0), (24.0, 0.0)))
+        }),
+        (104.0, 72.0, Color::WHITE, |e| {
+            e.set(CubicCurve::new((0.0, -24.0), (16.0, 24.0), (16.0, 0.0)))
+        }),
+    ];
+    for (x, y, color, shape) in shapes {
+        shape(
+            harness
+                .spawn()
+                .set(Transform::translate(x, y))
+                .set(Fill::new(color))
+                .set(Stroke::new(2.0, Color::GRAY)),
+        );
+    }
+
+    let mut path = vello::kurbo::BezPath::new();
+    path.move_to((0.0, 0.0));
+    path.quad_to((32.0, -16.0), (64.0, 0.0));
+    path.line_to((32.0, 16.0));
+    harness
+        .spawn()
+        .set(Transform::translate(32.0, 108.0))
+        .set(Fill::new(Color::LIME))
+        .set(PathShape::new(path));
+
+    assert_golden("shapes", &harness.render());
+}
+
+#[test]
+fn brushes() {
+    use vello::peniko::{Extend, Gradient};
+
+    let Some(harness) = Harness::new() else {
+        return;
+    };
+    let linear = Gradient::new_linear((-24.0, 0.0), (24.0, 0.0))
+        .with_stops([Color::RED, Color::YELLOW, Color::BLUE]);
+    harness
+        .spawn()
+        .set(Transform(
+            Affine::translate((36.0, 36.0)) * Affine::rotate(std::f64::consts::FRAC_PI_6),
+        ))
+        .set(Fill::new(linear))
+        .set(Rect::new(48.0, 48.0));
+
+    let radial = Gradient::new_radial((0.0, 0.0), 8.0)
+        .with_stops([Color::WHITE, Color::FUCHSIA])
+        .with_extend(Extend::Reflect);
+    harness
+        .spawn()
+        .set(Transform::translate(92.0, 36.0))
+        .set(Fill::new(radial).with_brush_transform(Affine::scale_non_uniform(2.0, 1.0)))
+        .set(Circle::new(28.0));
+
+    let sweep = Gradient::new_sweep((0.0, 0.0), 0.0, std::f32::consts::TAU)
+        .with_stops([Color::LIME, Color::AQUA, Color::LIME]);
+    harness
+        .spawn()
+        .set(Transform::translate(64.0, 96.0))
+        .set(Stroke::new(8.0, sweep))
+        .set(Ellipse::new(48.0, 20.0));
+
+    assert_golden("brushes", &harness.render());
+}
@@ -0,0 +1,209 @@
+//! Picking driven by `SyntheticInput` in a headless world, no GPU is needed.
+
+use flecs_ecs::prelude::*;
+use flecs_wgpu_demo::*;
+use winit::event::MouseButton;
+
+#[derive(Component, Default)]
+struct Clicks(u32);
+
+#[test]
+fn click_shape() {
+    let world = World::new();
+    world.set(Input::default());
+    world.set(Clicks::default());
+    world.import::<ApplicationModule>();
+    world.import::<VelloShapeModule>();
+    world.import::<PickingModule>();
+
+    observer!(world, Click, &Transform).each_iter(|it, _, _| {
+        it.world().get::<&mut Clicks>(|clicks| clicks.0 += 1);
+    });
+
+    // Stands in for a window, picking only needs its cursor
+    let window = world.entity_named("window").set(Cursor::default());
+    let scene = world
+        .entity_named("scene")
+        .child_of_id(window)
+        .set(VelloScene::default());
+    let shape = world
+        .entity_named("shape")
+        .child_of_id(scene)
+        .set(Transform::translate(50.0, 50.0))
+        .set(Fill::new(vello::peniko::Color::RED))
+        .set(Circle::new(20.0));
+    // Shapes are picked where they were last drawn
+    world.progress();
+
+    let input = SyntheticInput::new(window);
+    input.move_cursor(55.0, 45.0);
+    world.progress();
+    assert!(shape.has::<Hovered>());
+    assert!(!shape.has::<Pressed>());
+
+    input.press(MouseButton::Left);
+    world.progress();
+    assert!(shape.has::<Pressed>());
+    assert_eq!(world.get::<&Clicks>(|clicks| clicks.0), 0);
+
+    input.release(MouseButton::Left);
+    world.progress();
+    assert!(!shape.has::<Pressed>());
+    assert_eq!(world.get::<&Clicks>(|clicks| clicks.0), 1);
+
+    // Releasing away from the shape it was pressed on is not a click
+    input.press(MouseButton::Left);
+    world.progress();
+    input.move_cursor(5.0, 5.0);
+    world.progress();
+    assert!(!shape.has::<Hovered>());
+    input.release(MouseButton::Left);
+    world.progress();
+    assert_eq!(world.get::<&Clicks>(|clicks| clicks.0), 1);
+}
+
+#[derive(Component, Default)]
+struct Presses {
+    canvas: u32,
+    ui: u32,
+}
+
+#[test]
+fn higher_layer_takes_pointer() {
+    let world = World::new();
+    world.set(Input::default());
+    world.set(Presses::default());
+    world.import::<ApplicationModule>();
+    world.import::<VelloShapeModule>();
+    world.import::<PickingModule>();
+
+    system!(world, &mut Presses($), &Input($))
+        .kind::<flecs::pipeline::OnUpdate>()
+        .each(|(presses, input)| {
+            if input.layer(0).just_pressed(MouseButton::Left) {
+                presses.canvas += 1;
+            }
+            if input.layer(1).just_pressed(MouseButton::Left) {
+                presses.ui += 1;
+            }
+        });
+
+    let window = world.entity_named("window").set(Cursor::default());
+    let canvas = world
+        .entity_named("canvas")
+        .child_of_id(window)
+        .set(VelloScene::default());
+    let mut ui_scene = VelloScene::default();
+    ui_scene.layer = 1;
+    let ui = world
+        .entity_named("ui")
+        .child_of_id(window)
+        .set(ui_scene);
+    for (scene, x) in [(canvas, 50.0), (ui, 150.0)] {
+        world
+            .entity()
+            .child_of_id(scene)
+            .set(Transform::translate(x, 50.0))
+            .set(Fill::new(vello::peniko::Color::RED))
+            .set(Rect::new(40.0, 40.0));
+    }
+    world.progress();
+
+    // Over the canvas shape both layers see the press
+    let input = SyntheticInput::new(window);
+    input.move_cursor(50.0, 50.0).press(MouseButton::Left);
+    world.progress();
+    input.release(MouseButton::Left);
+    world.progress();
+    assert_eq!(world.get::<&Presses>(|presses| (presses.canvas, presses.ui)), (1, 1));
+
+    // Over the UI shape the canvas does not
+    input.move_cursor(150.0, 50.0).press(MouseButton::Left);
+    world.progress();
+    assert_eq!(world.get::<&Presses>(|presses| (presses.canvas, presses.ui)), (1, 2));
+}
+
+// A shape type defined outside the crate
+#[derive(Component)]
+struct Diamond(f64);
+
+impl VelloShape for Diamond {
+    fn shape(&self) -> impl vello::kurbo::Shape {
+        let r = self.0;
+        let mut path = vello::kurbo::BezPath::new();
+        path.move_to((0.0, -r));
+        path.line_to((r, 0.0));
+        path.line_to((0.0, r));
+        path.line_to((-r, 0.0));
+        path.close_path();
+        path
+    }
+}
+
+#[test]
+fn custom_shape() {
+    let world = World::new();
+    world.set(Input::default());
+    world.import::<ApplicationModule>();
+    world.import::<VelloShapeModule>();
+    world.import::<PickingModule>();
+    Diamond::systems(&world);
+
+    let window = world.entity_named("window").set(Cursor::default());
+    let scene = world
+        .entity_named("scene")
+        .child_of_id(window)
+        .set(VelloScene::default());
+    let shape = world
+        .entity_named("shape")
+        .child_of_id(scene)
+        .set(Transform::translate(50.0, 50.0))
+        .set(Fill::new(vello::peniko::Color::RED))
+        .set(Diamond(20.0));
+    world.progress();
+
+    let input = SyntheticInput::new(window);
+    input.move_cursor(55.0, 55.0);
+    world.progress();
+    assert!(shape.has::<Hovered>());
+
+    // Inside the bounding box but outside the diamond
+    input.move_cursor(68.0, 68.0);
+    world.progress();
+    assert!(!shape.has::<Hovered>());
+}
+
+#[test]
+fn later_scene_on_same_layer_is_on_top() {
+    let world = World::new();
+    world.set(Input::default());
+    world.import::<ApplicationModule>();
+    world.import::<VelloShapeModule>();
+    world.import::<PickingModule>();
+
+    let window = world.entity_named("window").set(Cursor::default());
+    let shapes: Vec<_> = ["below", "above"]
+        .into_iter()
+        .map(|name| {
+            let scene = world
+                .entity_named(name)
+                .child_of_id(window)
+                .set(VelloScene::default());
+            // The shape below is painted higher within its own scene
+            let z = if name == "below" { 10 } else { 0 };
+            world
+                .entity()
+                .child_of_id(scene)
+                .set(Transform::translate(50.0, 50.0))
+                .set(ZIndex(z))
+                .set(Fill::new(vello::peniko::Color::RED))
+                .set(Rect::new(40.0, 40.0))
+        })
+        .collect();
+    world.progress();
+
+    SyntheticInput::new(window).move_cursor(50.0, 50.0);
+    world.progress();
+    assert!(!shapes[0].has::<Hovered>());
+    assert!(shapes[1].has::<Hovered>());
+}
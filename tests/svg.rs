@@ -0,0 +1,43 @@
+//! SVG export in a headless world, no GPU is needed.
+
+use flecs_ecs::prelude::*;
+use flecs_wgpu_demo::*;
+use vello::peniko::{Color, Gradient};
+
+#[test]
+fn filled_and_stroked_shape() {
+    let world = World::new();
+    world.import::<VelloShapeModule>();
+    world.import::<SvgModule>();
+
+    let scene = world.entity_named("scene").set(VelloScene::default());
+    let group = world
+        .entity_named("group")
+        .child_of_id(scene)
+        .set(Transform::translate(10.0, 20.0));
+    let gradient =
+        Gradient::new_linear((0.0, 0.0), (10.0, 0.0)).with_stops([Color::RED, Color::BLUE]);
+    world
+        .entity_named("shape")
+        .child_of_id(group)
+        .set(Transform::translate(5.0, 0.0))
+        .set(Fill::new(Color::RED))
+        .set(Stroke::new(2.0, gradient))
+        .set(Rect::new(20.0, 10.0));
+    world.progress();
+
+    let svg = scene_to_svg(scene);
+    // The group's transform is applied to the shape's
+    assert!(svg.contains("transform=\"matrix(1 0 0 1 15 20)\""), "{svg}");
+    assert!(svg.contains(" fill=\"#ff0000\" fill-rule=\"nonzero\""), "{svg}");
+    assert!(svg.contains(" stroke=\"url(#gradient0)\" stroke-width=\"2\""), "{svg}");
+
+    let start = svg.find("<defs>").expect("no gradient defs");
+    let defs = &svg[start..svg.find("</defs>").unwrap()];
+    assert!(
+        defs.contains("<linearGradient id=\"gradient0\" gradientUnits=\"userSpaceOnUse\" spreadMethod=\"pad\" x1=\"0\" y1=\"0\" x2=\"10\" y2=\"0\">"),
+        "{defs}"
+    );
+    assert!(defs.contains("offset=\"0\" stop-color=\"#ff0000\""), "{defs}");
+    assert!(defs.contains("offset=\"1\" stop-color=\"#0000ff\""), "{defs}");
+}
@@ -0,0 +1,48 @@
+//! Transform propagation in a headless world, no GPU is needed.
+
+use flecs_ecs::prelude::*;
+use flecs_wgpu_demo::*;
+use vello::kurbo::{Affine, Vec2};
+
+fn global(entity: EntityView) -> Option<Affine> {
+    entity.try_get::<&GlobalTransform>(|global| **global)
+}
+
+#[test]
+fn reparent_and_remove() {
+    let world = World::new();
+    world.import::<VelloShapeModule>();
+
+    let scene = world.entity_named("scene").set(VelloScene::default());
+    let left = world
+        .entity_named("left")
+        .child_of_id(scene)
+        .set(Transform::translate(10.0, 0.0));
+    let right = world
+        .entity_named("right")
+        .child_of_id(scene)
+        .set(Transform::translate(100.0, 0.0));
+    let shape = world
+        .entity_named("shape")
+        .child_of_id(left)
+        .set(Transform::translate(0.0, 5.0));
+    let child = world
+        .entity_named("child")
+        .child_of_id(shape)
+        .set(Transform::translate(1.0, 0.0));
+    world.progress();
+    assert_eq!(global(shape), Some(Affine::translate(Vec2::new(10.0, 5.0))));
+    assert_eq!(global(child), Some(Affine::translate(Vec2::new(11.0, 5.0))));
+
+    // Reparenting moves the subtree without touching its transforms
+    shape.child_of_id(right);
+    world.progress();
+    assert_eq!(global(shape), Some(Affine::translate(Vec2::new(100.0, 5.0))));
+    assert_eq!(global(child), Some(Affine::translate(Vec2::new(101.0, 5.0))));
+
+    // Without a transform the shape is no longer placed, its child moves up a level
+    shape.remove::<Transform>();
+    world.progress();
+    assert_eq!(global(shape), None);
+    assert_eq!(global(child), Some(Affine::translate(Vec2::new(101.0, 0.0))));
+}